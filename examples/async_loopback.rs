@@ -0,0 +1,26 @@
+//! Streams words through a cohort from a tokio task without blocking the runtime.
+//!
+//! Run with `cargo run --example async_loopback --features async,mock`. No
+//! hardware is needed: a `LoopbackAccelerator` plays the accelerator and
+//! hands every word back unchanged.
+
+use cohort::{CohortBuilder, LoopbackAccelerator};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let config = CohortBuilder::new().capacity(64).batch_size(8);
+    let accel = LoopbackAccelerator::<u64>::new(&config).unwrap();
+    let cohort = accel.cohort();
+
+    // SAFETY: Only this task pushes onto and pops from the cohort.
+    for i in (0..64).step_by(2) {
+        unsafe { cohort.push_async(&i, &(i + 1)) }.await;
+    }
+    cohort.flush();
+
+    let (mut a, mut b) = (0, 0);
+    for _ in (0..64).step_by(2) {
+        unsafe { cohort.pop_async(&mut a, &mut b) }.await;
+        println!("{a} {b}");
+    }
+}
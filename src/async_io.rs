@@ -0,0 +1,65 @@
+//! Pushing and popping from async code without blocking the runtime.
+//!
+//! Enabled by the `async` feature.
+
+use crate::error::Error;
+use crate::Cohort;
+
+impl<S: Copy, R: Copy> Cohort<S, R> {
+    /// Sends an element to the accelerator.
+    ///
+    /// Yields to the tokio runtime between attempts while the sending end is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics on a cohort made with [`register_source`](Cohort::register_source),
+    /// like [`push`](Cohort::push).
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Cohort::push).
+    pub async unsafe fn push_async(&self, elem1: &S, elem2: &S) {
+        loop {
+            match unsafe { self.try_push(elem1, elem2) } {
+                Ok(()) => return,
+                Err(Error::Full) => tokio::task::yield_now().await,
+                Err(err) => panic!("{err}"),
+            }
+        }
+    }
+
+    /// Receives an element from the accelerator.
+    ///
+    /// Yields to the tokio runtime between attempts while the receiving end is empty.
+    ///
+    /// # Panics
+    ///
+    /// In verify mode, panics on a sequence mismatch like [`pop`](Cohort::pop).
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Cohort::pop).
+    pub async unsafe fn pop_async(&self, elem1: &mut R, elem2: &mut R) {
+        loop {
+            match unsafe { self.try_pop(elem1, elem2) } {
+                Ok(()) => return,
+                Err(Error::Empty | Error::PartialElement) => tokio::task::yield_now().await,
+                Err(err) => panic!("{err}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Cohort;
+
+    // Registering a source needs the stubbed syscall, so unlike the other
+    // async tests this one cannot live in tests/.
+    #[tokio::test]
+    #[should_panic(expected = "does not carry data")]
+    async fn push_async_panics_on_a_source() {
+        let cohort = unsafe { Cohort::<u64>::register_source(0, 4, 2) }.unwrap();
+        unsafe { cohort.push_async(&1, &2) }.await;
+    }
+}
@@ -0,0 +1,416 @@
+//! Configuration of a [`Cohort`] before it is registered.
+
+use core::pin::Pin;
+
+use crate::error::{Error, Result};
+use crate::fifo::{self, CohortFifo};
+use crate::util::DEFAULT_SPINS_BEFORE_YIELD;
+use crate::{log_unregister_error, Cohort, BACKOFF_COUNTER_VAL};
+
+/// Configures and registers a [`Cohort`].
+///
+/// Every setting is validated by [`build`](Self::build) so a bad
+/// configuration is reported as an [`Error`](crate::Error) instead of a panic.
+///
+/// ```no_run
+/// # use cohort::CohortBuilder;
+/// // SAFETY: No other cohorts are associated with id 0.
+/// let cohort = unsafe {
+///     CohortBuilder::new()
+///         .id(0)
+///         .capacity(128)
+///         .batch_size(16)
+///         .backoff(480)
+///         .build::<u64, u64>()
+/// }
+/// .unwrap();
+/// cohort.producer().unwrap().push(&10, &20);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CohortBuilder {
+    pub(crate) id: u8,
+    pub(crate) capacity: usize,
+    pub(crate) batch_size: usize,
+    pub(crate) flush_threshold_ratio: Option<f32>,
+    pub(crate) out_batch_size: Option<usize>,
+    pub(crate) backoff: u64,
+    pub(crate) custom_data: u64,
+    pub(crate) spins_before_yield: u32,
+    pub(crate) on_unregister_error: fn(u8, Error),
+    pub(crate) verify: bool,
+    pub(crate) strict_ordering: bool,
+    pub(crate) fast_wrap: bool,
+}
+
+impl CohortBuilder {
+    /// Creates a builder for cohort 0 with a capacity of 64, a batch size of 8
+    /// and the default backoff.
+    pub fn new() -> Self {
+        CohortBuilder {
+            id: 0,
+            capacity: 64,
+            batch_size: 8,
+            flush_threshold_ratio: None,
+            out_batch_size: None,
+            backoff: BACKOFF_COUNTER_VAL,
+            custom_data: 0,
+            spins_before_yield: DEFAULT_SPINS_BEFORE_YIELD,
+            on_unregister_error: log_unregister_error,
+            verify: false,
+            strict_ordering: false,
+            fast_wrap: false,
+        }
+    }
+
+    /// Sets the id of the cohort.
+    pub fn id(mut self, id: u8) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Sets the number of elements each queue can hold.
+    ///
+    /// Must be even and no less than the batch size.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets the number of elements pushed before the accelerator is told about them.
+    ///
+    /// Must be even and at least 2.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Sets the batch size to a fraction of the capacity instead of a fixed
+    /// number of elements, e.g. 0.25 to flush once a queue is a quarter full.
+    ///
+    /// The batch size becomes `ceil(capacity * ratio)`, rounded up to an even
+    /// number, and overrides [`batch_size`](Self::batch_size). The ratio must
+    /// be greater than 0 and at most 1.
+    pub fn flush_threshold_ratio(mut self, ratio: f32) -> Self {
+        self.flush_threshold_ratio = Some(ratio);
+        self
+    }
+
+    /// Sets the number of elements [`Cohort::pop_batch`] receives at a time.
+    ///
+    /// Should match the size of the chunks in which the accelerator publishes
+    /// its output. Has the same constraints as the batch size, which it
+    /// defaults to.
+    pub fn out_batch_size(mut self, out_batch_size: usize) -> Self {
+        self.out_batch_size = Some(out_batch_size);
+        self
+    }
+
+    /// Sets the backoff counter the accelerator uses while polling the queues.
+    ///
+    /// The kernel hands the value to the Cohort engine as is. It is roughly
+    /// how many cycles the engine waits before polling a queue again after
+    /// finding nothing new in it, so it trades latency for power: small
+    /// values, down to a few tens, pick up a push almost at once but keep
+    /// the engine polling memory, while values in the thousands let it idle
+    /// at the cost of up to that many cycles before it notices new work.
+    /// Defaults to 240, a middle ground for accelerators fed in bursts.
+    pub fn backoff(mut self, backoff: u64) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the value the custom data word holds when the cohort is
+    /// registered, 0 by default.
+    ///
+    /// Accelerators that read the word as soon as the engine starts, such as
+    /// ones taking a configuration word, need it set here: a
+    /// [`Cohort::set_custom_data`] after registration may come too late.
+    /// Ignored in verify mode, where the word starts at 0.
+    pub fn custom_data(mut self, custom_data: u64) -> Self {
+        self.custom_data = custom_data;
+        self
+    }
+
+    /// Sets how many spin rounds the blocking `push`/`pop` calls make before
+    /// they start yielding the thread.
+    ///
+    /// Each round spins about twice as long as the previous one. Pass
+    /// `u32::MAX` to never yield, for latency-sensitive workloads that own a core.
+    pub fn spins_before_yield(mut self, spins_before_yield: u32) -> Self {
+        self.spins_before_yield = spins_before_yield;
+        self
+    }
+
+    /// Fences every queue pointer access with `SeqCst` on both sides.
+    ///
+    /// By default reading the head or hw_tail acquires and publishing them
+    /// releases, which is all the accelerator protocol needs on most
+    /// platforms. Turn this on if an accelerator misses updates without it.
+    pub fn strict_ordering(mut self, strict_ordering: bool) -> Self {
+        self.strict_ordering = strict_ordering;
+        self
+    }
+
+    /// Rounds the queue buffers up to a power of two slots, so the queue
+    /// indices wrap with a mask instead of a division.
+    ///
+    /// The queues still hold at most the configured capacity, but each
+    /// buffer can take up to twice the memory; see
+    /// [`CohortFifo::new_fast_wrap_in`](crate::CohortFifo::new_fast_wrap_in).
+    pub fn fast_wrap(mut self, fast_wrap: bool) -> Self {
+        self.fast_wrap = fast_wrap;
+        self
+    }
+
+    /// Turns on sequence checking of the elements moved through the cohort,
+    /// for bringing up a new accelerator that answers every element with one
+    /// element.
+    ///
+    /// The cohort counts the elements it pushes and pops, and the custom
+    /// data word is handed to the accelerator, which must publish its output
+    /// and then raise the word by the number of elements it just answered.
+    /// Every element the accelerator takes from the sender must be counted,
+    /// including any whose answer it then fails to publish: the check only
+    /// compares counts, so an element dropped and never counted goes
+    /// unnoticed.
+    ///
+    /// After every pop, on any of the pop methods, no more elements may have
+    /// been popped than pushed, and every element the accelerator counted
+    /// must have been popped or be waiting in the receiver; otherwise the
+    /// pop fails with [`Error::SequenceMismatch`](crate::Error::SequenceMismatch),
+    /// or panics where it cannot fail. Elements still in flight are fine.
+    /// Off by default; the custom data word is then left to the user.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Sets what is called with the cohort id and error when unregistering
+    /// fails as the cohort is dropped.
+    ///
+    /// Defaults to printing the error to stderr. Call
+    /// [`Cohort::unregister`] instead of dropping to get the error directly.
+    pub fn on_unregister_error(mut self, callback: fn(u8, Error)) -> Self {
+        self.on_unregister_error = callback;
+        self
+    }
+
+    /// Checks the configuration without registering anything.
+    pub fn validate(&self) -> Result<()> {
+        let batch_size = self.resolved_batch_size()?;
+        fifo::validate(self.capacity, batch_size)?;
+        fifo::validate(self.capacity, self.out_batch_size.unwrap_or(batch_size))
+    }
+
+    /// The batch size, computed from the flush threshold ratio if one is set.
+    pub(crate) fn resolved_batch_size(&self) -> Result<usize> {
+        let Some(ratio) = self.flush_threshold_ratio else {
+            return Ok(self.batch_size);
+        };
+        if !(ratio > 0.0 && ratio <= 1.0) {
+            return Err(Error::FlushThresholdRatioOutOfRange);
+        }
+        let batch_size = (self.capacity as f64 * ratio as f64).ceil() as usize;
+        Ok(batch_size.next_multiple_of(2))
+    }
+
+    pub(crate) fn resolved_out_batch_size(&self) -> Result<usize> {
+        match self.out_batch_size {
+            Some(out_batch_size) => Ok(out_batch_size),
+            None => self.resolved_batch_size(),
+        }
+    }
+
+    /// Applies the settings that live on each queue to `fifo`.
+    pub(crate) fn tune<T: Copy>(&self, fifo: CohortFifo<T>) -> CohortFifo<T> {
+        fifo.with_spins_before_yield(self.spins_before_yield)
+            .with_strict_ordering(self.strict_ordering)
+    }
+
+    /// Validates the configuration and registers the cohort.
+    ///
+    /// Fails with [`Error::RegistrationFailed`](crate::Error::RegistrationFailed)
+    /// if the kernel rejects the cohort.
+    ///
+    /// # Safety
+    ///
+    /// The cohort id must not currently be in use.
+    ///
+    /// `S` is the type sent to the accelerator and `R` the type received from it.
+    pub unsafe fn build<S: Copy, R: Copy>(self) -> Result<Pin<Box<Cohort<S, R>>>> {
+        let cohort = Cohort::with_config(&self)?;
+        cohort.cohort_mn_register()?;
+        Ok(cohort)
+    }
+}
+
+impl Default for CohortBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The basic settings of a cohort, from [`Cohort::config`], for registering
+/// an identical one under another id with [`Cohort::register_with_config`].
+///
+/// Use [`Cohort::to_builder`] instead to carry over the finer settings of
+/// a [`CohortBuilder`] too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CohortConfig {
+    /// The number of elements each queue can hold.
+    pub capacity: usize,
+    /// The number of elements pushed before the accelerator is told about them.
+    pub batch_size: usize,
+    /// The backoff counter handed to the register syscall.
+    pub backoff: u64,
+}
+
+impl From<CohortConfig> for CohortBuilder {
+    fn from(config: CohortConfig) -> Self {
+        CohortBuilder::new()
+            .capacity(config.capacity)
+            .batch_size(config.batch_size)
+            .backoff(config.backoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CohortBuilder, CohortConfig};
+    use crate::error::Error;
+    use crate::sys::stub::{self, Call};
+    use crate::Cohort;
+
+    #[test]
+    fn rejects_invalid_config() {
+        let builder = CohortBuilder::new();
+        assert!(matches!(
+            builder.clone().batch_size(0).validate(),
+            Err(Error::BatchSizeTooSmall)
+        ));
+        assert!(matches!(
+            builder.clone().batch_size(5).validate(),
+            Err(Error::BatchSizeNotEven)
+        ));
+        assert!(matches!(
+            builder.clone().out_batch_size(3).validate(),
+            Err(Error::BatchSizeNotEven)
+        ));
+        assert!(matches!(
+            builder.clone().capacity(4).batch_size(8).validate(),
+            Err(Error::CapacityLessThanBatchSize)
+        ));
+        assert!(matches!(
+            builder.clone().capacity(9).batch_size(8).validate(),
+            Err(Error::CapacityNotEven(9))
+        ));
+
+        let built = unsafe { builder.capacity(3).build::<u64, u64>() };
+        assert!(matches!(built, Err(Error::CapacityLessThanBatchSize)));
+        assert!(stub::calls().is_empty());
+    }
+
+    #[test]
+    fn flush_threshold_ratio_sets_batch_size() {
+        let builder = CohortBuilder::new().capacity(64);
+        for ratio in [0.0, -0.5, 1.5, f32::NAN] {
+            assert_eq!(
+                builder.clone().flush_threshold_ratio(ratio).validate(),
+                Err(Error::FlushThresholdRatioOutOfRange)
+            );
+        }
+        let tiny = builder.clone().flush_threshold_ratio(0.01);
+        assert_eq!(tiny.resolved_batch_size(), Ok(2));
+
+        let cohort = Cohort::<u64>::with_config(&builder.flush_threshold_ratio(0.5)).unwrap();
+        assert_eq!(cohort.batch_size(), 32);
+        for n in 0..15 {
+            unsafe { cohort.push(&n, &n) };
+        }
+        assert_eq!(cohort.sender().available(), 0);
+        unsafe { cohort.push(&15, &15) };
+        assert_eq!(cohort.sender().available(), 32);
+    }
+
+    #[test]
+    fn custom_data_is_set_before_register() {
+        let cohort =
+            unsafe { CohortBuilder::new().custom_data(0xC0DE).build::<u64, u64>() }.unwrap();
+        assert_eq!(stub::custom_data_at_register(), [0xC0DE]);
+        assert_eq!(cohort.get_custom_data(), 0xC0DE);
+    }
+
+    #[test]
+    fn backoff_reaches_register() {
+        let cohort = unsafe {
+            CohortBuilder::new()
+                .id(4)
+                .capacity(16)
+                .batch_size(4)
+                .backoff(1000)
+                .build::<u64, u64>()
+        }
+        .unwrap();
+        assert_eq!(stub::calls(), [Call::Register { backoff: 1000 }]);
+        assert_eq!(cohort.backoff(), 1000);
+
+        unsafe { cohort.push(&1, &2) };
+        assert_eq!(cohort.sender_len(), 2);
+    }
+
+    #[test]
+    fn config_replicates_a_cohort() {
+        let first = unsafe {
+            CohortBuilder::new()
+                .id(1)
+                .capacity(32)
+                .batch_size(4)
+                .out_batch_size(8)
+                .backoff(500)
+                .strict_ordering(true)
+                .build::<u64, u64>()
+        }
+        .unwrap();
+        let second = unsafe { first.to_builder().id(2).build::<u64, u64>() }.unwrap();
+        assert_eq!(second.capacity(), first.capacity());
+        assert_eq!(second.batch_size(), 4);
+        assert_eq!(second.out_batch_size(), 8);
+        assert_eq!(second.backoff(), 500);
+        assert!(second.to_builder().strict_ordering);
+        assert_eq!(stub::calls(), [Call::Register { backoff: 500 }; 2]);
+    }
+
+    #[test]
+    fn register_with_config_copies_settings() {
+        let first = unsafe {
+            CohortBuilder::new()
+                .capacity(32)
+                .batch_size(4)
+                .backoff(500)
+                .build::<u64, u64>()
+        }
+        .unwrap();
+        let config = first.config();
+        assert_eq!(
+            config,
+            CohortConfig {
+                capacity: 32,
+                batch_size: 4,
+                backoff: 500
+            }
+        );
+
+        let second = unsafe { Cohort::<u64>::register_with_config(1, &config) }.unwrap();
+        assert_eq!(second.capacity(), first.capacity());
+        assert_eq!(second.batch_size(), first.batch_size());
+        assert_eq!(second.config(), config);
+        assert_eq!(stub::calls(), [Call::Register { backoff: 500 }; 2]);
+    }
+
+    #[test]
+    fn activate_uses_configured_backoff() {
+        let cohort = Cohort::<u64>::with_config(&CohortBuilder::new().backoff(7)).unwrap();
+        unsafe { cohort.as_ref().activate() }.unwrap();
+        assert_eq!(stub::calls(), [Call::Register { backoff: 7 }]);
+    }
+}
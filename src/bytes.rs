@@ -0,0 +1,405 @@
+//! Moving byte strings, byte streams and fixed-endian integers through a
+//! cohort of byte-array words.
+
+use core::pin::Pin;
+use std::io::{self, Read, Write};
+
+use crate::Cohort;
+
+/// A [`Cohort`] of `N`-byte words that sends and receives byte slices of any length.
+///
+/// Each slice is sent as a header word holding its length in bytes, as a
+/// little-endian `u64` in the first 8 bytes, followed by the slice cut into
+/// `N`-byte words with the final word zero-padded. Carrying the length in
+/// band lets several messages be queued at once, and leaves the custom data
+/// word to the accelerator. The accelerator must answer with messages in
+/// the same format, such as a loopback does.
+///
+/// Like any [`Cohort`], `N` must be a multiple of 8.
+pub struct ByteCohort<const N: usize> {
+    cohort: Pin<Box<Cohort<[u8; N]>>>,
+}
+
+impl<const N: usize> ByteCohort<N> {
+    /// Wraps a registered cohort.
+    pub fn new(cohort: Pin<Box<Cohort<[u8; N]>>>) -> Self {
+        ByteCohort { cohort }
+    }
+
+    /// Returns the wrapped cohort.
+    pub fn cohort(&self) -> &Cohort<[u8; N]> {
+        &self.cohort
+    }
+
+    /// Sends `bytes` to the accelerator and flushes it.
+    ///
+    /// May block if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Cohort::push`]: no other push onto the wrapped cohort may
+    /// run at the same time.
+    pub unsafe fn push_bytes(&self, bytes: &[u8]) {
+        let mut header = [0; N];
+        header[..8].copy_from_slice(&(bytes.len() as u64).to_le_bytes());
+        unsafe { self.cohort.push_one(&header) };
+
+        let (words, rest) = bytes.as_chunks::<N>();
+        unsafe { self.cohort.push_slice(words) };
+        if !rest.is_empty() {
+            let mut last = [0; N];
+            last[..rest.len()].copy_from_slice(rest);
+            unsafe { self.cohort.push_one(&last) };
+        }
+        self.cohort.flush();
+    }
+
+    /// Receives the next message from the accelerator into `out`.
+    ///
+    /// Writes as much of the message as fits in `out` and returns the number
+    /// of bytes written. The whole message is always received, so if `out`
+    /// is too short the rest of it is dropped and the next call still starts
+    /// on the following message. May block until every word of the message
+    /// has arrived.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Cohort::pop`]: no other pop from the wrapped cohort may run
+    /// at the same time.
+    pub unsafe fn pop_bytes(&self, out: &mut [u8]) -> usize {
+        let mut header = [0; N];
+        unsafe { self.cohort.pop_one(&mut header) };
+        let len = u64::from_le_bytes(header[..8].try_into().unwrap()) as usize;
+
+        let mut word = [0; N];
+        let mut written = 0;
+        for start in (0..len).step_by(N) {
+            unsafe { self.cohort.pop_one(&mut word) };
+            if let Some(dest) = out.get_mut(start..) {
+                let count = dest.len().min(len - start).min(N);
+                dest[..count].copy_from_slice(&word[..count]);
+                written += count;
+            }
+        }
+        written
+    }
+}
+
+/// Writes a byte stream to the accelerator through a [`Cohort`] of `N`-byte
+/// words, so any [`io::Write`] based encoder can feed it.
+///
+/// Bytes are gathered into words and each word is sent once it is complete.
+/// Writes never block: once the sending end is full they fail with
+/// [`io::ErrorKind::WouldBlock`] without taking any bytes.
+///
+/// [`flush`](Write::flush) sends a partial last word zero-padded to `N`
+/// bytes and makes everything sent visible to the accelerator, so only
+/// flush at boundaries where the reading side can skip padding.
+pub struct CohortWriter<'a, const N: usize, R: Copy = [u8; N]> {
+    cohort: &'a Cohort<[u8; N], R>,
+    word: [u8; N],
+    len: usize,
+}
+
+impl<'a, const N: usize, R: Copy> CohortWriter<'a, N, R> {
+    /// Creates a writer sending to `cohort`.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Cohort::push`], for as long as the writer lives: nothing
+    /// else may push onto `cohort` while it writes.
+    pub unsafe fn new(cohort: &'a Cohort<[u8; N], R>) -> Self {
+        CohortWriter {
+            cohort,
+            word: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Sends the buffered word if it is complete. Returns false if it could
+    /// not be sent for lack of room.
+    fn send_complete_word(&mut self) -> bool {
+        if self.len < N {
+            return true;
+        }
+        // SAFETY: The caller of new promised nothing else pushes.
+        if unsafe { self.cohort.try_push_one(&self.word) }.is_err() {
+            return false;
+        }
+        self.len = 0;
+        true
+    }
+}
+
+impl<const N: usize, R: Copy> Write for CohortWriter<'_, N, R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() && self.send_complete_word() {
+            let count = (N - self.len).min(buf.len() - written);
+            self.word[self.len..self.len + count].copy_from_slice(&buf[written..written + count]);
+            self.len += count;
+            written += count;
+        }
+        // Hand over the last word as soon as it is complete; if there is no
+        // room it stays buffered for the next write or flush.
+        self.send_complete_word();
+
+        if written == 0 && !buf.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.len > 0 {
+            self.word[self.len..].fill(0);
+            self.len = N;
+            if !self.send_complete_word() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+        }
+        self.cohort.flush();
+        Ok(())
+    }
+}
+
+/// Reads the byte stream an accelerator answers with through a [`Cohort`]
+/// of `N`-byte words, so any [`io::Read`] based decoder can consume it.
+///
+/// Reads never block: when no word is available they fail with
+/// [`io::ErrorKind::WouldBlock`]. They never report the end of the stream
+/// either, since the accelerator may always publish more.
+pub struct CohortReader<'a, S: Copy, const N: usize> {
+    cohort: &'a Cohort<S, [u8; N]>,
+    word: [u8; N],
+    pos: usize,
+}
+
+impl<'a, S: Copy, const N: usize> CohortReader<'a, S, N> {
+    /// Creates a reader receiving from `cohort`.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Cohort::pop`], for as long as the reader lives: nothing else
+    /// may pop from `cohort` while it reads.
+    pub unsafe fn new(cohort: &'a Cohort<S, [u8; N]>) -> Self {
+        CohortReader {
+            cohort,
+            word: [0; N],
+            pos: N,
+        }
+    }
+}
+
+impl<S: Copy, const N: usize> Read for CohortReader<'_, S, N> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        while read < buf.len() {
+            if self.pos == N {
+                // SAFETY: The caller of new promised nothing else pops.
+                if unsafe { self.cohort.try_pop_one(&mut self.word) }.is_err() {
+                    break;
+                }
+                self.pos = 0;
+            }
+            let count = (N - self.pos).min(buf.len() - read);
+            buf[read..read + count].copy_from_slice(&self.word[self.pos..self.pos + count]);
+            self.pos += count;
+            read += count;
+        }
+
+        if read == 0 && !buf.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        Ok(read)
+    }
+}
+
+impl<R: Copy> Cohort<[u8; 8], R> {
+    /// Sends a pair of `u64`s to the accelerator as little-endian words.
+    ///
+    /// May block if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Cohort::push).
+    pub unsafe fn push_u64_le(&self, elem1: u64, elem2: u64) {
+        unsafe { self.push(&elem1.to_le_bytes(), &elem2.to_le_bytes()) };
+    }
+
+    /// Sends a pair of `u64`s to the accelerator as big-endian words.
+    ///
+    /// May block if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Cohort::push).
+    pub unsafe fn push_u64_be(&self, elem1: u64, elem2: u64) {
+        unsafe { self.push(&elem1.to_be_bytes(), &elem2.to_be_bytes()) };
+    }
+}
+
+impl<S: Copy> Cohort<S, [u8; 8]> {
+    /// Receives a pair of little-endian words from the accelerator as `u64`s.
+    ///
+    /// May block if the receiving end is empty.
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Cohort::pop).
+    pub unsafe fn pop_u64_le(&self) -> (u64, u64) {
+        let (elem1, elem2) = unsafe { self.pop_words() };
+        (u64::from_le_bytes(elem1), u64::from_le_bytes(elem2))
+    }
+
+    /// Receives a pair of big-endian words from the accelerator as `u64`s.
+    ///
+    /// May block if the receiving end is empty.
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Cohort::pop).
+    pub unsafe fn pop_u64_be(&self) -> (u64, u64) {
+        let (elem1, elem2) = unsafe { self.pop_words() };
+        (u64::from_be_bytes(elem1), u64::from_be_bytes(elem2))
+    }
+
+    unsafe fn pop_words(&self) -> ([u8; 8], [u8; 8]) {
+        let (mut elem1, mut elem2) = ([0; 8], [0; 8]);
+        unsafe { self.pop(&mut elem1, &mut elem2) };
+        (elem1, elem2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read, Write};
+
+    use super::{ByteCohort, CohortReader, CohortWriter};
+    use crate::Cohort;
+
+    #[test]
+    fn round_trips_unaligned_length() {
+        let cohort = unsafe { Cohort::<[u8; 8]>::register(0, 16, 4) }.unwrap();
+        let bytes = ByteCohort::new(cohort);
+
+        let message: Vec<u8> = (0..29).collect();
+        unsafe { bytes.push_bytes(&message) };
+        assert_eq!(bytes.cohort().get_custom_data(), 0);
+        bytes.cohort().loopback();
+
+        let mut out = [0xFF; 64];
+        assert_eq!(unsafe { bytes.pop_bytes(&mut out) }, 29);
+        assert_eq!(&out[..29], &message[..]);
+        // Padding in the last word does not leak past the true length.
+        assert!(out[29..].iter().all(|&b| b == 0xFF));
+        assert!(bytes.cohort().receiver_is_empty());
+    }
+
+    #[test]
+    fn queued_messages_keep_their_lengths() {
+        let cohort = unsafe { Cohort::<[u8; 8]>::register(0, 16, 4) }.unwrap();
+        let bytes = ByteCohort::new(cohort);
+
+        let long: Vec<u8> = (0..29).collect();
+        let short = [0xAB; 10];
+        let last = [0xCD; 3];
+        unsafe { bytes.push_bytes(&long) };
+        unsafe { bytes.push_bytes(&short) };
+        unsafe { bytes.push_bytes(&last) };
+        bytes.cohort().loopback();
+
+        let mut out = [0; 64];
+        assert_eq!(unsafe { bytes.pop_bytes(&mut out) }, 29);
+        assert_eq!(&out[..29], &long[..]);
+        // Too short an output drops the rest of the message, not the next one.
+        let mut out = [0; 4];
+        assert_eq!(unsafe { bytes.pop_bytes(&mut out) }, 4);
+        assert_eq!(out, [0xAB; 4]);
+        let mut out = [0; 64];
+        assert_eq!(unsafe { bytes.pop_bytes(&mut out) }, 3);
+        assert_eq!(&out[..3], &last[..]);
+        assert!(bytes.cohort().receiver_is_empty());
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Reading {
+        sensor: u16,
+        value: f64,
+        label: [u8; 5],
+    }
+
+    impl Reading {
+        const LEN: usize = 15;
+
+        fn encode(&self, out: &mut impl Write) -> std::io::Result<()> {
+            out.write_all(&self.sensor.to_le_bytes())?;
+            out.write_all(&self.value.to_le_bytes())?;
+            out.write_all(&self.label)
+        }
+
+        fn decode(input: &mut impl Read) -> std::io::Result<Self> {
+            let mut bytes = [0; Self::LEN];
+            input.read_exact(&mut bytes)?;
+            Ok(Reading {
+                sensor: u16::from_le_bytes(bytes[..2].try_into().unwrap()),
+                value: f64::from_le_bytes(bytes[2..10].try_into().unwrap()),
+                label: bytes[10..].try_into().unwrap(),
+            })
+        }
+    }
+
+    #[test]
+    fn io_adapters_round_trip_a_struct() {
+        let cohort = unsafe { Cohort::<[u8; 8]>::register(0, 4, 2) }.unwrap();
+        let mut writer = unsafe { CohortWriter::new(&cohort) };
+        let mut reader = unsafe { CohortReader::new(&cohort) };
+        let mut byte = [0];
+        assert_eq!(
+            reader.read(&mut byte).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+
+        let reading = Reading {
+            sensor: 7,
+            value: -1.5,
+            label: *b"probe",
+        };
+        reading.encode(&mut writer).unwrap();
+        writer.flush().unwrap();
+        cohort.loopback();
+        assert_eq!(Reading::decode(&mut reader).unwrap(), reading);
+        // The padding of the last word follows the struct.
+        assert_eq!(reader.read(&mut byte).unwrap(), 1);
+        assert_eq!(byte, [0]);
+        assert_eq!(
+            reader.read(&mut byte).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+
+        // The sender holds four words, and a fifth stays buffered.
+        assert_eq!(writer.write(&[1; 48]).unwrap(), 40);
+        assert_eq!(
+            writer.write(&[1]).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+    }
+
+    #[test]
+    fn u64_words_keep_byte_order() {
+        let cohort = unsafe { Cohort::<[u8; 8]>::register(0, 16, 2) }.unwrap();
+        unsafe { cohort.push_u64_le(0x1122334455667788, 1) };
+        unsafe { cohort.push_u64_be(0x1122334455667788, 1) };
+        cohort.loopback();
+
+        let (mut elem1, mut elem2) = ([0; 8], [0; 8]);
+        cohort.peek(&mut elem1, &mut elem2).unwrap();
+        assert_eq!(elem1, [0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11]);
+        assert_eq!(elem2, [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(unsafe { cohort.pop_u64_le() }, (0x1122334455667788, 1));
+
+        cohort.peek(&mut elem1, &mut elem2).unwrap();
+        assert_eq!(elem1, [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88]);
+        assert_eq!(unsafe { cohort.pop_u64_be() }, (0x1122334455667788, 1));
+    }
+}
@@ -0,0 +1,49 @@
+//! Iteration over the elements waiting in a receiver.
+
+use crate::Cohort;
+
+/// An iterator over the elements the accelerator has made available.
+///
+/// Created by [`Cohort::drain`]. The iterator never waits: it ends on the
+/// first call to `next` that finds the receiver empty. In verify mode
+/// `next` panics on a sequence mismatch, like [`Cohort::pop`].
+pub struct Drain<'a, S: Copy, R: Copy = S> {
+    cohort: &'a Cohort<S, R>,
+}
+
+impl<'a, S: Copy, R: Copy> Drain<'a, S, R> {
+    pub(crate) fn new(cohort: &'a Cohort<S, R>) -> Self {
+        Drain { cohort }
+    }
+}
+
+impl<S: Copy, R: Copy> Iterator for Drain<'_, S, R> {
+    type Item = R;
+
+    fn next(&mut self) -> Option<R> {
+        // try_take reads the hw_tail, so data that arrived since the last
+        // call is seen.
+        let elem = self.cohort.receiver.try_take().ok()?;
+        self.cohort.assert_popped(1);
+        Some(elem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Cohort;
+
+    #[test]
+    fn drain_collects_loopback() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 16, 2) }.unwrap();
+        for n in 0..5 {
+            unsafe { cohort.push(&(2 * n), &(2 * n + 1)) };
+        }
+        cohort.loopback();
+
+        let drained: Vec<_> = unsafe { cohort.drain() }.collect();
+        assert_eq!(drained, (0..10).collect::<Vec<_>>());
+        assert!(cohort.receiver_is_empty());
+        assert_eq!(unsafe { cohort.drain() }.next(), None);
+    }
+}
@@ -0,0 +1,332 @@
+//! Errors returned by Cohort operations.
+
+use core::fmt;
+
+/// The error type for Cohort operations.
+///
+/// With the `serde` feature it serializes as an object naming the variant
+/// in `kind`, with any payload in `detail`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "kind", content = "detail")
+)]
+pub enum Error {
+    /// The sending queue has no room for the pushed elements.
+    Full,
+    /// The receiving queue holds no elements at all.
+    Empty,
+    /// The receiving queue holds some elements, but fewer than a whole pair
+    /// or transaction; the rest may still be on the way.
+    PartialElement,
+    /// The batch size is less than 2.
+    BatchSizeTooSmall,
+    /// The batch size is not even.
+    BatchSizeNotEven,
+    /// The capacity is less than the batch size.
+    CapacityLessThanBatchSize,
+    /// The contained capacity is not even.
+    CapacityNotEven(usize),
+    /// The cohort was registered as a sink or a source, without the queue
+    /// for this direction.
+    DirectionDisabled,
+    /// The flush threshold ratio is not in `(0, 1]`.
+    FlushThresholdRatioOutOfRange,
+    /// The capacity is too large for the accelerator to address, or the
+    /// buffer it needs would not fit in the address space.
+    CapacityTooLarge,
+    /// A mapped buffer does not start on a 128-byte boundary.
+    MisalignedBuffer,
+    /// The allocator could not provide a queue buffer of the contained size.
+    AllocationFailed {
+        /// The size of the buffer in bytes.
+        bytes: usize,
+    },
+    /// A batch pushed as a whole does not hold exactly one batch of pairs.
+    BatchLengthMismatch {
+        /// The number of pairs in one batch.
+        expected: usize,
+        /// The number of pairs given.
+        got: usize,
+    },
+    /// The element type has the contained size in bytes, which is not a
+    /// whole number of accelerator words.
+    UnsupportedElementSize(usize),
+    /// In verify mode, more elements were received than pushed, or fewer
+    /// than the accelerator reported answering, so elements were duplicated
+    /// or dropped.
+    SequenceMismatch {
+        /// The number of elements pushed, or the number the accelerator
+        /// reported answering.
+        expected: u64,
+        /// The number of elements received so far, counting those waiting
+        /// in the receiver when checking against the accelerator's count.
+        got: u64,
+    },
+    /// No Cohort device could be opened at the given path, or its name does
+    /// not end in a cohort id.
+    DeviceNotFound,
+    /// The calling thread could not be pinned to a CPU, failing with the
+    /// contained errno.
+    AffinityFailed(i32),
+    /// The readiness descriptor could not be created, failing with the
+    /// contained errno.
+    ReadinessFailed(i32),
+    /// The cohort is already registered, so registering it again would hand
+    /// its queues to the kernel twice.
+    AlreadyRegistered,
+    /// The kernel rejected the register syscall with the contained errno.
+    RegistrationFailed(i32),
+    /// The kernel rejected the unregister syscall with the contained errno.
+    UnregisterFailed(i32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Full => write!(f, "queue is full"),
+            Error::Empty => write!(f, "queue is empty"),
+            Error::PartialElement => write!(f, "queue holds only part of a transaction"),
+            Error::BatchSizeTooSmall => write!(f, "`batch_size` cannot be less than 2"),
+            Error::BatchSizeNotEven => write!(f, "`batch_size` must be even"),
+            Error::CapacityLessThanBatchSize => {
+                write!(f, "`capacity` cannot be less than `batch_size`")
+            }
+            Error::CapacityNotEven(capacity) => {
+                write!(f, "`capacity` must be divisible by 2, got {capacity}")
+            }
+            Error::DirectionDisabled => {
+                write!(f, "cohort does not carry data in this direction")
+            }
+            Error::FlushThresholdRatioOutOfRange => {
+                write!(
+                    f,
+                    "flush threshold ratio must be greater than 0 and at most 1"
+                )
+            }
+            Error::CapacityTooLarge => {
+                write!(
+                    f,
+                    "`capacity` must be less than {} and fit in memory",
+                    u32::MAX
+                )
+            }
+            Error::MisalignedBuffer => write!(f, "mapped buffer is not 128-byte aligned"),
+            Error::UnsupportedElementSize(size) => {
+                write!(
+                    f,
+                    "element size of {size} bytes is not a multiple of the word size"
+                )
+            }
+            Error::AllocationFailed { bytes } => {
+                write!(f, "failed to allocate a queue buffer of {bytes} bytes")
+            }
+            Error::BatchLengthMismatch { expected, got } => {
+                write!(f, "expected a batch of {expected} pairs but got {got}")
+            }
+            Error::SequenceMismatch { expected, got } => {
+                write!(f, "expected {expected} elements in sequence, found {got}")
+            }
+            Error::DeviceNotFound => write!(f, "no cohort device found at the given path"),
+            Error::AffinityFailed(errno) => {
+                write!(f, "setting the CPU affinity failed with errno {errno}")
+            }
+            Error::ReadinessFailed(errno) => {
+                write!(
+                    f,
+                    "creating the readiness descriptor failed with errno {errno}"
+                )
+            }
+            Error::AlreadyRegistered => write!(f, "cohort is already registered"),
+            Error::RegistrationFailed(errno) => {
+                write!(f, "register syscall failed with errno {errno}")
+            }
+            Error::UnregisterFailed(errno) => {
+                write!(f, "unregister syscall failed with errno {errno}")
+            }
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// Lets cohort operations be used with `?` in functions returning
+/// [`std::io::Result`].
+///
+/// The cohort error is kept as the inner error, so it can be recovered with
+/// [`std::io::Error::downcast`].
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        use std::io::ErrorKind;
+
+        let kind = match err {
+            Error::Full | Error::Empty | Error::PartialElement => ErrorKind::WouldBlock,
+            Error::BatchSizeTooSmall
+            | Error::BatchSizeNotEven
+            | Error::CapacityLessThanBatchSize
+            | Error::CapacityNotEven(_)
+            | Error::FlushThresholdRatioOutOfRange
+            | Error::CapacityTooLarge
+            | Error::MisalignedBuffer
+            | Error::BatchLengthMismatch { .. }
+            | Error::UnsupportedElementSize(_) => ErrorKind::InvalidInput,
+            Error::AllocationFailed { .. } => ErrorKind::OutOfMemory,
+            Error::DirectionDisabled => ErrorKind::Unsupported,
+            Error::SequenceMismatch { .. } => ErrorKind::InvalidData,
+            Error::DeviceNotFound => ErrorKind::NotFound,
+            Error::AlreadyRegistered => ErrorKind::AlreadyExists,
+            Error::AffinityFailed(errno)
+            | Error::ReadinessFailed(errno)
+            | Error::RegistrationFailed(errno)
+            | Error::UnregisterFailed(errno) => std::io::Error::from_raw_os_error(errno).kind(),
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+/// A specialized [`Result`](core::result::Result) type for Cohort operations.
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::io::{self, ErrorKind};
+
+    use super::Error;
+
+    #[test]
+    fn compares_variants_and_payloads() {
+        let variants = [
+            Error::Full,
+            Error::Empty,
+            Error::PartialElement,
+            Error::BatchSizeTooSmall,
+            Error::BatchSizeNotEven,
+            Error::CapacityLessThanBatchSize,
+            Error::CapacityNotEven(3),
+            Error::DirectionDisabled,
+            Error::FlushThresholdRatioOutOfRange,
+            Error::CapacityTooLarge,
+            Error::MisalignedBuffer,
+            Error::UnsupportedElementSize(12),
+            Error::AllocationFailed { bytes: 128 },
+            Error::BatchLengthMismatch {
+                expected: 4,
+                got: 3,
+            },
+            Error::SequenceMismatch {
+                expected: 1,
+                got: 2,
+            },
+            Error::DeviceNotFound,
+            Error::AffinityFailed(22),
+            Error::ReadinessFailed(22),
+            Error::AlreadyRegistered,
+            Error::RegistrationFailed(22),
+            Error::UnregisterFailed(22),
+        ];
+        for (i, a) in variants.iter().enumerate() {
+            for (j, b) in variants.iter().enumerate() {
+                assert_eq!(a == b, i == j, "{a:?} vs {b:?}");
+            }
+        }
+
+        assert_ne!(
+            Error::UnsupportedElementSize(12),
+            Error::UnsupportedElementSize(4)
+        );
+        assert_ne!(
+            Error::SequenceMismatch {
+                expected: 1,
+                got: 2
+            },
+            Error::SequenceMismatch {
+                expected: 1,
+                got: 3
+            }
+        );
+        assert_ne!(Error::RegistrationFailed(1), Error::RegistrationFailed(2));
+        assert_ne!(Error::CapacityNotEven(3), Error::CapacityNotEven(5));
+    }
+
+    #[test]
+    fn converts_to_io_error_kind() {
+        let cases = [
+            (Error::Full, ErrorKind::WouldBlock),
+            (Error::Empty, ErrorKind::WouldBlock),
+            (Error::PartialElement, ErrorKind::WouldBlock),
+            (Error::BatchSizeTooSmall, ErrorKind::InvalidInput),
+            (Error::BatchSizeNotEven, ErrorKind::InvalidInput),
+            (Error::CapacityLessThanBatchSize, ErrorKind::InvalidInput),
+            (Error::CapacityNotEven(3), ErrorKind::InvalidInput),
+            (Error::DirectionDisabled, ErrorKind::Unsupported),
+            (
+                Error::FlushThresholdRatioOutOfRange,
+                ErrorKind::InvalidInput,
+            ),
+            (Error::CapacityTooLarge, ErrorKind::InvalidInput),
+            (Error::MisalignedBuffer, ErrorKind::InvalidInput),
+            (Error::UnsupportedElementSize(12), ErrorKind::InvalidInput),
+            (
+                Error::AllocationFailed { bytes: 128 },
+                ErrorKind::OutOfMemory,
+            ),
+            (
+                Error::BatchLengthMismatch {
+                    expected: 4,
+                    got: 3,
+                },
+                ErrorKind::InvalidInput,
+            ),
+            (
+                Error::SequenceMismatch {
+                    expected: 1,
+                    got: 2,
+                },
+                ErrorKind::InvalidData,
+            ),
+            (Error::DeviceNotFound, ErrorKind::NotFound),
+            (Error::AffinityFailed(libc::EINVAL), ErrorKind::InvalidInput),
+            (Error::ReadinessFailed(libc::ENOMEM), ErrorKind::OutOfMemory),
+            (Error::AlreadyRegistered, ErrorKind::AlreadyExists),
+            (
+                Error::RegistrationFailed(libc::EBUSY),
+                ErrorKind::ResourceBusy,
+            ),
+            (
+                Error::UnregisterFailed(libc::EPERM),
+                ErrorKind::PermissionDenied,
+            ),
+        ];
+        for (err, kind) in cases {
+            assert_eq!(io::Error::from(err).kind(), kind);
+        }
+
+        let err = io::Error::from(Error::Full);
+        assert!(matches!(err.downcast::<Error>(), Ok(Error::Full)));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serializes_tagged() {
+        let cases = [
+            (Error::Full, r#"{"kind":"Full"}"#),
+            (
+                Error::RegistrationFailed(16),
+                r#"{"kind":"RegistrationFailed","detail":16}"#,
+            ),
+            (
+                Error::SequenceMismatch {
+                    expected: 1,
+                    got: 2,
+                },
+                r#"{"kind":"SequenceMismatch","detail":{"expected":1,"got":2}}"#,
+            ),
+        ];
+        for (err, json) in cases {
+            assert_eq!(serde_json::to_string(&err).unwrap(), json);
+            assert_eq!(serde_json::from_str::<Error>(json).unwrap(), err);
+        }
+    }
+}
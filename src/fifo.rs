@@ -1,26 +1,94 @@
-use crate::util::Aligned;
+use crate::error::{Error, Result};
+use crate::util::{prefetch_read, Aligned, Backoff, DEFAULT_SPINS_BEFORE_YIELD};
 use core::ptr::NonNull;
-use std::{
-    alloc::{alloc_zeroed, dealloc, Layout},
-    cell::UnsafeCell,
+use core::{
+    alloc::{GlobalAlloc, Layout},
     mem, ptr,
 };
-use std::sync::atomic::{fence, Ordering};
+use core::sync::atomic::{AtomicU32, Ordering};
 
 
-#[repr(packed)]
+/// Size in bytes of the words the accelerator moves through the queues.
+///
+/// Every element must be made up of a whole number of these words.
+pub const WORD_SIZE: usize = 8;
+
+/// Checks that a fifo can be built with the given capacity and batch size.
+pub(crate) fn validate(capacity: usize, batch_size: usize) -> Result<()> {
+    if batch_size < 2 {
+        return Err(Error::BatchSizeTooSmall);
+    }
+    if !batch_size.is_multiple_of(2) {
+        return Err(Error::BatchSizeNotEven);
+    }
+    if capacity < batch_size {
+        return Err(Error::CapacityLessThanBatchSize);
+    }
+    if !capacity.is_multiple_of(2) {
+        return Err(Error::CapacityNotEven(capacity));
+    }
+    // The buffer holds one slot more than the capacity and its size is
+    // handed to the accelerator as a u32.
+    if capacity >= u32::MAX as usize {
+        return Err(Error::CapacityTooLarge);
+    }
+    Ok(())
+}
+
+/// The queue pointers of a [`CohortFifo`] at one moment, for monitoring.
+///
+/// Taken with [`CohortFifo::snapshot`]. The pointers are read one after
+/// another rather than atomically together, so a snapshot of a fifo in use
+/// may combine values from slightly different moments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FifoSnapshot {
+    /// Index of the next element to pop.
+    pub head: usize,
+    /// Index after the last element pushed by software.
+    pub sw_tail: usize,
+    /// Index after the last element published to the other side.
+    pub hw_tail: usize,
+    /// Number of elements the fifo can hold.
+    pub capacity: usize,
+}
+
+#[repr(C, packed)]
 pub struct Meta<T> {
     buffer: NonNull<T>,
     _elem_size: u32,
     buffer_size: u32,
 }
 
+/// The allocator a [`CohortFifo`] uses unless told otherwise: the global
+/// allocator of the `alloc` crate.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultAlloc;
+
+unsafe impl GlobalAlloc for DefaultAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { alloc::alloc::alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        unsafe { alloc::alloc::alloc_zeroed(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { alloc::alloc::dealloc(ptr, layout) }
+    }
+}
+
+/// A single queue shared with a Cohort accelerator.
+///
+/// A [`Cohort`](crate::Cohort) pairs two of these, one the software pushes
+/// onto and one the accelerator pushes onto. The buffer is allocated from `A`.
 #[repr(C)]
-pub struct CohortFifo<T: Copy + std::fmt::Debug> {
+pub struct CohortFifo<T: Copy, A: GlobalAlloc = DefaultAlloc> {
     // Cohort requires that these fields be 128 byte alligned and in the specified order.
-    head: Aligned<UnsafeCell<u32>>,
+    head: Aligned<AtomicU32>,
     meta: Aligned<Meta<T>>,
-    hw_tail: Aligned<UnsafeCell<u32>>,
+    hw_tail: Aligned<AtomicU32>,
 
     
     //Extra fields not used by cohort accelerators
@@ -29,204 +97,955 @@ pub struct CohortFifo<T: Copy + std::fmt::Debug> {
     batch_size: usize,
     // This is the tail used internally by the software to keep track of the
     // true number of elements pushed to the queue
-    sw_tail: Aligned<UnsafeCell<u32>>,
-    
+    sw_tail: Aligned<AtomicU32>,
+    // At least num_elems: the occupancy last read plus every element pushed
+    // since, so pushes only read the head the accelerator moves once this
+    // reaches the batch size.
+    max_elems: AtomicU32,
+    // The number of elements the sender may push. Without fast wrap the
+    // buffer holds exactly one more.
+    capacity: usize,
+    // Whether the buffer size is a power of two, so indices wrap with a mask.
+    fast_wrap: bool,
+    // How long the blocking calls spin before they start yielding the thread.
+    spins_before_yield: u32,
+    // Whether every pointer access is fenced with SeqCst.
+    strict_ordering: bool,
+    // Where the buffer came from.
+    alloc: A,
+    // False if the buffer was mapped in by someone else and must not be
+    // freed on drop.
+    owns_buffer: bool,
 }
 
-impl<T: Copy + std::fmt::Debug> CohortFifo<T> {
-    // Creates new fifo.
-    pub fn new(capacity: usize, batch_size: usize) -> Result<Self, &'static str> {
-        if (batch_size < 2){
-            return Err("Arg `batch_size` cannot be less than 2")
-        }
+// The accelerator finds the queue pointers at these offsets.
+const _: () = {
+    assert!(mem::offset_of!(CohortFifo<u64>, head) == 0);
+    assert!(mem::offset_of!(CohortFifo<u64>, meta) == 128);
+    assert!(mem::offset_of!(CohortFifo<u64>, hw_tail) == 256);
+    assert!(mem::size_of::<AtomicU32>() == mem::size_of::<u32>());
+};
+
+impl<T: Copy> CohortFifo<T> {
+    /// Creates a new fifo whose buffer comes from the global allocator.
+    ///
+    /// The element size must be a non-zero multiple of [`WORD_SIZE`].
+    pub fn new(capacity: usize, batch_size: usize) -> Result<Self> {
+        Self::new_in(capacity, batch_size, DefaultAlloc)
+    }
+
+    /// Returns the smallest capacity that is valid with `batch_size`: the
+    /// batch size itself rounded up to an even number, and at least 2.
+    ///
+    /// The batch size still has to be valid on its own.
+    pub const fn min_valid_capacity(batch_size: usize) -> usize {
+        let capacity = if batch_size < 2 { 2 } else { batch_size };
+        capacity.next_multiple_of(2)
+    }
+
+    /// Checks a configuration without allocating anything.
+    ///
+    /// Fails with the same [`Error`] that [`new`](Self::new) would for this
+    /// element type, capacity and batch size.
+    pub fn is_valid_config(capacity: usize, batch_size: usize) -> Result<()> {
+        Self::check_config(capacity, batch_size)
+    }
+
+    /// Creates a stand-in for a queue a cohort does not use: a single-slot
+    /// buffer with a capacity of 0, so it is always full and always empty,
+    /// but still gives the accelerator valid pointers.
+    #[cfg(feature = "std")]
+    pub(crate) fn placeholder() -> Result<Self> {
+        Self::alloc_in(0, 1, 2, DefaultAlloc)
+    }
+
+    /// Creates a new fifo like [`new`](Self::new), rounding the capacity up
+    /// to the nearest valid one instead of failing.
+    ///
+    /// The capacity used is the smallest even number no less than either
+    /// `requested` or `batch_size`, and is returned alongside the fifo. The
+    /// batch size is still checked as is.
+    pub fn with_capacity_rounded(requested: usize, batch_size: usize) -> Result<(Self, usize)> {
+        let capacity = requested.max(batch_size).next_multiple_of(2);
+        Ok((Self::new(capacity, batch_size)?, capacity))
+    }
 
-        if (batch_size % 2 != 0){
-            return Err("Arg `batch_size` must be even")
+    /// Creates a new fifo like [`new`](Self::new) whose buffer size is a
+    /// power of two; see [`new_fast_wrap_in`](CohortFifo::new_fast_wrap_in).
+    pub fn new_fast_wrap(capacity: usize, batch_size: usize) -> Result<Self> {
+        Self::new_fast_wrap_in(capacity, batch_size, DefaultAlloc)
+    }
+
+    /// Creates a fifo over a buffer mapped in from elsewhere, such as a
+    /// region shared with an accelerator driver in another process.
+    ///
+    /// The buffer is used as is, not zeroed, and is never freed by the fifo.
+    /// Fails with [`Error::MisalignedBuffer`] unless `buffer` is 128-byte aligned.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must point to at least `capacity + 1` writable elements of
+    /// `T` that stay mapped for as long as the fifo exists.
+    pub unsafe fn from_mapped(buffer: NonNull<u8>, capacity: usize, batch_size: usize) -> Result<Self> {
+        Self::check_config(capacity, batch_size)?;
+        if !(buffer.as_ptr() as usize).is_multiple_of(128) {
+            return Err(Error::MisalignedBuffer);
         }
+        Ok(Self::from_parts(buffer.cast(), capacity, capacity + 1, batch_size, DefaultAlloc, false))
+    }
+}
 
-        if(capacity < batch_size) {
-            return Err("Arg `capacity` cannot be less than `batch_size`")
+impl<T: Copy, A: GlobalAlloc> CohortFifo<T, A> {
+    /// Creates a new fifo whose buffer comes from `alloc`.
+    ///
+    /// The element size must be a non-zero multiple of [`WORD_SIZE`]. Fails
+    /// with [`Error::AllocationFailed`] if `alloc` cannot provide the buffer.
+    pub fn new_in(capacity: usize, batch_size: usize, alloc: A) -> Result<Self> {
+        Self::check_config(capacity, batch_size)?;
+        Self::alloc_in(capacity, capacity + 1, batch_size, alloc)
+    }
+
+    /// Creates a new fifo whose buffer size is rounded up to a power of two,
+    /// so indices wrap with a mask instead of a division.
+    ///
+    /// The fifo still holds at most `capacity` elements, but the buffer,
+    /// which must be `capacity + 1` slots otherwise, can take up to twice
+    /// the memory. The accelerator reads the buffer size from the fifo, so
+    /// it wraps at the larger size too.
+    ///
+    /// Fails with [`Error::AllocationFailed`] if `alloc` cannot provide the
+    /// buffer.
+    pub fn new_fast_wrap_in(capacity: usize, batch_size: usize, alloc: A) -> Result<Self> {
+        Self::check_config(capacity, batch_size)?;
+        let buffer_size = (capacity + 1).next_power_of_two();
+        if buffer_size > u32::MAX as usize {
+            return Err(Error::CapacityTooLarge);
         }
-        // Capacity must 
-        if(capacity %2 != 0){
-            return Err("Arg `capacity` must be divisible by 2.");
+        let mut fifo = Self::alloc_in(capacity, buffer_size, batch_size, alloc)?;
+        fifo.fast_wrap = true;
+        Ok(fifo)
+    }
+
+    fn alloc_in(capacity: usize, buffer_size: usize, batch_size: usize, alloc: A) -> Result<Self> {
+        let layout = Self::layout(buffer_size)?;
+        let buffer = NonNull::new(unsafe { alloc.alloc_zeroed(layout) })
+            .ok_or(Error::AllocationFailed { bytes: layout.size() })?;
+        Ok(Self::from_parts(buffer.cast(), capacity, buffer_size, batch_size, alloc, true))
+    }
+
+    /// Checks the configuration and the element size.
+    fn check_config(capacity: usize, batch_size: usize) -> Result<()> {
+        validate(capacity, batch_size)?;
+        let elem_size = mem::size_of::<T>();
+        if elem_size == 0 || !elem_size.is_multiple_of(WORD_SIZE) {
+            return Err(Error::UnsupportedElementSize(elem_size));
         }
-        let buffer = unsafe {
-            let buffer_size = capacity + 1;
-            let layout = Layout::array::<T>(buffer_size).unwrap();
-            let aligned = layout.align_to(128).unwrap();
-            NonNull::new(alloc_zeroed(aligned)).unwrap()
-        };
+        Self::layout(capacity + 1)?;
+        Ok(())
+    }
 
-        Ok(CohortFifo {
-            head: Aligned(UnsafeCell::new(0)),
+    fn from_parts(
+        buffer: NonNull<T>,
+        capacity: usize,
+        buffer_size: usize,
+        batch_size: usize,
+        alloc: A,
+        owns_buffer: bool,
+    ) -> Self {
+        CohortFifo {
+            head: Aligned(AtomicU32::new(0)),
             meta: Aligned(Meta {
-                buffer: buffer.cast(),
+                buffer,
                 _elem_size: mem::size_of::<T>() as u32,
-                buffer_size: (capacity + 1) as u32,
+                buffer_size: buffer_size as u32,
             }),
-            hw_tail: Aligned(UnsafeCell::new(0)),
+            hw_tail: Aligned(AtomicU32::new(0)),
 
 
             batch_size,
-            sw_tail: Aligned(UnsafeCell::new(0)),
-        })
+            sw_tail: Aligned(AtomicU32::new(0)),
+            max_elems: AtomicU32::new(0),
+            capacity,
+            fast_wrap: false,
+            spins_before_yield: DEFAULT_SPINS_BEFORE_YIELD,
+            strict_ordering: false,
+            alloc,
+            owns_buffer,
+        }
+    }
+
+    /// Layout of a buffer with `buffer_size` slots.
+    ///
+    /// Fails with [`Error::CapacityTooLarge`] if the buffer would not fit
+    /// in the address space.
+    fn layout(buffer_size: usize) -> Result<Layout> {
+        Layout::array::<T>(buffer_size)
+            .and_then(|layout| layout.align_to(128))
+            .map_err(|_| Error::CapacityTooLarge)
+    }
+
+    /// Sets how many spin rounds the blocking calls make before yielding the thread.
+    ///
+    /// `u32::MAX` keeps them spinning forever.
+    pub fn with_spins_before_yield(mut self, spins_before_yield: u32) -> Self {
+        self.spins_before_yield = spins_before_yield;
+        self
+    }
+
+    /// Makes every head and tail access `SeqCst` rather than the default
+    /// acquire on loading and release on publishing.
+    pub fn with_strict_ordering(mut self, strict_ordering: bool) -> Self {
+        self.strict_ordering = strict_ordering;
+        self
     }
 
-    pub fn try_push(&self, elem1: &T, elem2: &T) -> Result<(), ()> {
+    /// Pushes a single element to the fifo.
+    ///
+    /// Will fail if the fifo is full.
+    pub fn try_push_one(&self, elem: &T) -> Result<()> {
         if self.is_full() {
-            return Err(());
+            return Err(Error::Full);
         }
-        // println!("-----SENDER QUEUE------");
-        // self.print_queue();
-        let sw_tail = self.sw_tail();
-        unsafe {
-            (*self.buffer().as_ptr())[sw_tail] = *elem1;
-            (*self.buffer().as_ptr())[(sw_tail+1) %self.buffer_size()] = *elem2;
+        self.write_one(elem);
+        self.flush_if_batch_full(1);
+        Ok(())
+    }
+
+    /// Pushes a single element to the fifo, spinning until there is room.
+    pub fn push_one(&self, elem: &T) {
+        let mut backoff = self.backoff();
+        while self.try_push_one(elem).is_err() {
+            backoff.snooze();
         }
+    }
 
-        self.set_sw_tail((sw_tail + 2) % self.buffer_size());
+    /// Pushes a pair of elements to the fifo.
+    ///
+    /// Either both elements are pushed or neither is.
+    pub fn try_push(&self, elem1: &T, elem2: &T) -> Result<()> {
+        self.try_push_n(&[*elem1, *elem2])
+    }
 
-        // Make sure the hw_tail keeps up when we go over the batch
-        // size, this optimizes the accelerator by allowing it 
-        // to process large batches at a time.
-        if self.num_elems() >= self.batch_size {
-            self.set_hw_tail(self.sw_tail());
+    /// Pushes `N` elements to the fifo, for accelerators whose transactions
+    /// are `N` words long.
+    ///
+    /// Either all the elements are pushed or none is.
+    pub fn try_push_n<const N: usize>(&self, elems: &[T; N]) -> Result<()> {
+        if self.capacity() - self.num_elems() < N {
+            return Err(Error::Full);
+        }
+        for elem in elems {
+            self.write_one(elem);
+        }
+
+        // Only flush once the whole transaction is written so the
+        // accelerator never observes part of one.
+        self.flush_if_batch_full(N);
+        Ok(())
+    }
+
+    /// Pushes exactly one batch of pairs and flushes once they are all written.
+    ///
+    /// `pairs` must hold `batch_size / 2` pairs, or this fails with
+    /// [`Error::BatchLengthMismatch`]. Either the whole batch is pushed or,
+    /// if it does not fit, nothing is and this fails with [`Error::Full`].
+    /// Elements pushed before and not yet flushed are published along with
+    /// the batch.
+    pub fn try_push_batch(&self, pairs: &[(T, T)]) -> Result<()> {
+        let expected = self.batch_size / 2;
+        if pairs.len() != expected {
+            return Err(Error::BatchLengthMismatch {
+                expected,
+                got: pairs.len(),
+            });
+        }
+        if self.capacity() - self.num_elems() < self.batch_size {
+            return Err(Error::Full);
+        }
+        for (elem1, elem2) in pairs {
+            self.write_one(elem1);
+            self.write_one(elem2);
+        }
+        self.flush();
+        Ok(())
+    }
+
+    /// Pushes `N` elements to the fifo, spinning until there is room for all of them.
+    pub fn push_n<const N: usize>(&self, elems: &[T; N]) {
+        let mut backoff = self.backoff();
+        while self.try_push_n(elems).is_err() {
+            backoff.snooze();
+        }
+    }
+
+    /// Pushes a pair of elements built in place by `f`.
+    ///
+    /// `f` is handed mutable references straight into the two reserved
+    /// slots, which are zeroed first, so large elements are not copied.
+    /// The tail only advances once `f` returns; if it panics the slots are
+    /// left unpublished.
+    ///
+    /// Will fail without calling `f` if the fifo is full.
+    pub fn try_push_with(&self, f: impl FnOnce(&mut T, &mut T)) -> Result<()> {
+        if self.capacity() - self.num_elems() < 2 {
+            return Err(Error::Full);
         }
 
+        let sw_tail = self.sw_tail();
+        let next = self.advance(sw_tail, 1);
+        let (first, second) = (self.slot(sw_tail), self.slot(next));
+        unsafe {
+            // Like the rest of the buffer, the slots rely on all-zero bytes
+            // being a valid element.
+            ptr::write_bytes(first, 0, 1);
+            ptr::write_bytes(second, 0, 1);
+            f(&mut *first, &mut *second);
+        }
+        self.set_sw_tail(self.advance(sw_tail, 2));
+
+        self.flush_if_batch_full(2);
         Ok(())
     }
 
     /// Pushes an element to the fifo.
     pub fn push(&self, elem1: &T, elem2: &T) {
-        while self.try_push(elem1, elem2).is_err() {}
+        let mut backoff = self.backoff();
+        while self.try_push(elem1, elem2).is_err() {
+            backoff.snooze();
+        }
     }
 
-    pub fn try_pop(&self, elem1: &mut T, elem2: &mut T) -> Result<(), ()> {
-        // If we're popping that means we're a receiver queue
-        // And we don't need to worry about batch sizes so just automatically
-        // update the sw_tail to the hw_tail before doing anything
-        self.set_sw_tail(self.hw_tail());
+    /// Pushes as many elements from `data` as currently fit in the fifo.
+    ///
+    /// Returns the number of elements written, which is zero if the fifo is full.
+    pub fn try_push_slice(&self, data: &[T]) -> usize {
+        let count = data.len().min(self.capacity() - self.num_elems());
+        if count == 0 {
+            return 0;
+        }
 
-        // Ensure that the accelerator has pushed at least two elements onto the queue
-        if self.is_empty() || self.num_elems() == 1 {
-            // println!("NUMBER OF ELEMS: {}", self.num_elems());
-            return Err(());
+        let sw_tail = self.sw_tail();
+        // Copy up to the end of the buffer, then wrap around to the front.
+        let first = count.min(self.buffer_size() - sw_tail);
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), self.slot(sw_tail), first);
+            ptr::copy_nonoverlapping(data.as_ptr().add(first), self.slot(0), count - first);
         }
-        // println!("---------RECEIVER QUEUE--------");
-        // self.print_queue();
+        self.set_sw_tail(self.advance(sw_tail, count));
+
+        self.flush_if_batch_full(count);
+        count
+    }
+
+    /// Pushes every element of `data` to the fifo, spinning while it is full.
+    pub fn push_slice(&self, data: &[T]) {
+        let mut pushed = 0;
+        let mut backoff = self.backoff();
+        while pushed < data.len() {
+            match self.try_push_slice(&data[pushed..]) {
+                0 => backoff.snooze(),
+                count => pushed += count,
+            }
+        }
+    }
+
+    /// Pops a single element from the fifo.
+    ///
+    /// Will fail if the fifo is empty.
+    pub fn try_pop_one(&self, elem: &mut T) -> Result<()> {
+        *elem = self.try_take()?;
+        Ok(())
+    }
+
+    /// Pops a single element from the fifo and returns it.
+    ///
+    /// Will fail if the fifo is empty.
+    pub fn try_take(&self) -> Result<T> {
+        if self.available() == 0 {
+            return Err(Error::Empty);
+        }
+        Ok(self.read_one())
+    }
+
+    /// Pops a single element from the fifo, spinning until one is available.
+    pub fn pop_one(&self, elem: &mut T) {
+        let mut backoff = self.backoff();
+        while self.try_pop_one(elem).is_err() {
+            backoff.snooze();
+        }
+    }
+
+    /// Pops a pair of elements from the fifo.
+    ///
+    /// Either both elements are popped or neither is. Fails with
+    /// [`Error::Empty`] if nothing is available, or with
+    /// [`Error::PartialElement`] if only the first element of the pair is.
+    pub fn try_pop(&self, elem1: &mut T, elem2: &mut T) -> Result<()> {
+        // If we're popping that means we're a receiver queue, so the
+        // accelerator is the producer and the hw_tail is the real tail.
+        // A lone trailing element can still be taken with try_pop_one.
+        [*elem1, *elem2] = self.try_pop_n()?;
+        Ok(())
+    }
+
+    /// Pops `N` elements from the fifo, for accelerators whose transactions
+    /// are `N` words long.
+    ///
+    /// Either all the elements are popped or none is. Fails with
+    /// [`Error::Empty`] if nothing is available, or with
+    /// [`Error::PartialElement`] if only part of a transaction is.
+    pub fn try_pop_n<const N: usize>(&self) -> Result<[T; N]> {
+        // Ensure that the accelerator has pushed a whole transaction onto the queue.
+        self.check_available(N)?;
+        let elems = core::array::from_fn(|_| self.read_one());
+        // The accelerator writes the next transaction right behind this
+        // one, so start pulling it in while the caller handles this one.
+        self.prefetch_head();
+        Ok(elems)
+    }
+
+    /// Pops `N` elements from the fifo, spinning until all of them are available.
+    pub fn pop_n<const N: usize>(&self) -> [T; N] {
+        let mut backoff = self.backoff();
+        loop {
+            if let Ok(elems) = self.try_pop_n() {
+                return elems;
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Copies out the pair of elements at the head without popping them.
+    ///
+    /// Will fail if the fifo holds fewer than two elements, with the same
+    /// [`Error::Empty`] or [`Error::PartialElement`] as
+    /// [`try_pop`](Self::try_pop).
+    pub fn try_peek(&self, elem1: &mut T, elem2: &mut T) -> Result<()> {
+        self.check_available(2)?;
         let head = self.head();
-        *elem1 = unsafe { (*self.buffer().as_ptr())[head]};
-        *elem2 = unsafe {(*self.buffer().as_ptr())[(head+1) %self.buffer_size()]};
+        *elem1 = self.read_slot(head);
+        *elem2 = self.read_slot(self.advance(head, 1));
+        Ok(())
+    }
+
+    /// Pops as many elements as are currently available into `out`.
+    ///
+    /// Returns the number of elements written, which is zero if the fifo is empty.
+    /// Unlike [`try_pop`](Self::try_pop) a lone trailing element is returned
+    /// rather than left behind.
+    pub fn pop_slice(&self, out: &mut [T]) -> usize {
+        let count = out.len().min(self.available());
+        if count == 0 {
+            return 0;
+        }
+        self.read_slice(&mut out[..count]);
+        count
+    }
 
-        self.set_head((head + 2) % self.buffer_size());
-        // println!("Head advanced to: {:?}", self.head());
+    /// Pops whole batches of [`batch_size`](Self::batch_size) elements into
+    /// `out`, spinning until at least one batch is available.
+    ///
+    /// Returns the number of elements written, always a multiple of the batch size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is shorter than one batch.
+    pub fn pop_batch(&self, out: &mut [T]) -> usize {
+        assert!(
+            out.len() >= self.batch_size,
+            "`out` holds {} elements, less than a batch of {}",
+            out.len(),
+            self.batch_size
+        );
+
+        let mut backoff = self.backoff();
+        let mut available = self.available();
+        while available < self.batch_size {
+            backoff.snooze();
+            available = self.available();
+        }
+
+        let count = available.min(out.len()) / self.batch_size * self.batch_size;
+        self.read_slice(&mut out[..count]);
+        count
+    }
+
+    /// Pops exactly `out.len()` elements from the fifo.
+    ///
+    /// Will fail without popping anything if fewer elements are available,
+    /// so a partial frame is never delivered.
+    pub fn try_pop_exact(&self, out: &mut [T]) -> Result<()> {
+        if self.available() < out.len() {
+            return Err(Error::Empty);
+        }
+        self.read_slice(out);
         Ok(())
     }
-    
 
     /// Pops an element from the fifo.
     pub fn pop(&self, elem1: &mut T, elem2: &mut T) {
+        let mut backoff = self.backoff();
         loop {
             if let Ok(()) = self.try_pop(elem1, elem2) {
                 break;
             }
+            backoff.snooze();
         }
     }
 
-    pub fn print_queue(&self){
-       unsafe{ println!("{:?}", self.buffer().as_ref())};
+    pub(crate) fn backoff(&self) -> Backoff {
+        Backoff::new(self.spins_before_yield)
     }
-    
+
+    /// Writes an element at the sw_tail and advances it by one slot.
+    ///
+    /// The caller must have checked that there is room for the element.
+    fn write_one(&self, elem: &T) {
+        let sw_tail = self.sw_tail();
+        self.write_slot(sw_tail, *elem);
+        self.set_sw_tail(self.advance(sw_tail, 1));
+    }
+
+    /// Reads the element at the head and advances it by one slot.
+    ///
+    /// The caller must have checked that the element is available.
+    fn read_one(&self) -> T {
+        let head = self.head();
+        let elem = self.read_slot(head);
+        self.set_head(self.advance(head, 1));
+        elem
+    }
+
+    /// Fills `out` from the head and advances it past them.
+    ///
+    /// The caller must have checked that `out.len()` elements are available.
+    fn read_slice(&self, out: &mut [T]) {
+        let count = out.len();
+        let head = self.head();
+        // Copy up to the end of the buffer, then wrap around to the front.
+        let first = count.min(self.buffer_size() - head);
+        unsafe {
+            ptr::copy_nonoverlapping(self.slot(head), out.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(self.slot(0), out.as_mut_ptr().add(first), count - first);
+        }
+        self.set_head(self.advance(head, count));
+    }
+
+    /// Prefetches the slot at the head, which the next pop reads first.
+    fn prefetch_head(&self) {
+        prefetch_read(self.slot(self.head()));
+    }
+
+    /// Checks that a transaction of `count` elements can be popped.
+    fn check_available(&self, count: usize) -> Result<()> {
+        match self.available() {
+            0 if count > 0 => Err(Error::Empty),
+            available if available < count => Err(Error::PartialElement),
+            _ => Ok(()),
+        }
+    }
+
+    /// Make sure the hw_tail keeps up when we go over the batch
+    /// size, this optimizes the accelerator by allowing it 
+    /// to process large batches at a time.
+    ///
+    /// Flushes once the occupancy reaches the batch size, after `pushed`
+    /// elements were written. The occupancy is only read once the elements
+    /// pushed since it was last read could have brought it there; until
+    /// then a push reads nothing the accelerator writes.
+    fn flush_if_batch_full(&self, pushed: usize) {
+        let max_elems = self.max_elems.load(Ordering::Relaxed) as usize + pushed;
+        if max_elems < self.batch_size {
+            self.max_elems.store(max_elems as u32, Ordering::Relaxed);
+            return;
+        }
+        let num_elems = self.num_elems();
+        if num_elems >= self.batch_size {
+            self.set_hw_tail(self.sw_tail());
+        }
+        self.max_elems.store(num_elems as u32, Ordering::Relaxed);
+    }
+
+    /// Makes every pushed element visible to the accelerator, even if the
+    /// current batch is not yet full.
+    pub fn flush(&self) {
+        self.set_hw_tail(self.sw_tail());
+        self.max_elems.store(self.num_elems() as u32, Ordering::Relaxed);
+    }
+
+    /// Empties the fifo by zeroing its head and tails, keeping the buffer.
+    ///
+    /// # Safety
+    ///
+    /// No accelerator may be reading or writing the pointers, i.e. the fifo
+    /// must not be registered with the kernel while this runs.
+    pub unsafe fn reset(&self) {
+        self.set_head(0);
+        self.set_sw_tail(0);
+        self.set_hw_tail(0);
+        self.max_elems.store(0, Ordering::Relaxed);
+    }
+
 
     /// True size of the underlying buffer.
     fn buffer_size(&self) -> usize {
-        // Should always be one more than the given capacity.
+        // At least one more than the given capacity.
         // The extra allocated slot in the buffer is used to determine whether the buffer is full.
         (self.meta.0.buffer_size) as usize
     }
 
-    /// TODO: BIG PROBLEM HERE!!!!! is_full() uses the sw_tail and so 
-    /// if we are a receiver queue and we use this without updating the 
-    /// sw_tail to the hw_tail set by the accelerator this function is inaccurate.
-    /// 
-    /// Currently we fix this by updating the hw_tail in try_pop before we call these
-    /// functions. But there must be a more elegant way to fix this...
+    /// True if the sender has filled the fifo.
+    ///
+    /// Like [`num_elems`](Self::num_elems) this counts up to the sw_tail, so
+    /// it is only meaningful on a sender queue.
     fn is_full(&self) -> bool {
-        (self.head() % self.buffer_size()) == ((self.sw_tail() + 1) % self.buffer_size())
+        self.num_elems() == self.capacity()
     }
 
-    /// TODO: BIG PROBLEM HERE!!!! SEE ABOVE COMMENT!!!!!
-    fn is_empty(&self) -> bool {
+    /// True if the sender has pushed nothing that is still in the fifo.
+    ///
+    /// Only meaningful on a sender queue; a receiver should check
+    /// [`available`](Self::available) instead.
+    pub fn is_empty(&self) -> bool {
         self.head() == self.sw_tail()
     }
 
-    /// TODO: BIG PROBLEM HERE!!!! SEE ABOVE COMMENT!!!!!
-    fn num_elems(&self) -> usize {
-        if self.head() >= self.sw_tail() {
-            return (self.head()-self.sw_tail()); 
+    /// Number of elements the sender has pushed that are still in the fifo,
+    /// whether or not they have been flushed.
+    ///
+    /// Only meaningful on a sender queue; a receiver should use
+    /// [`available`](Self::available) instead.
+    pub fn num_elems(&self) -> usize {
+        self.check_invariants(true);
+        self.elems_before(self.sw_tail())
+    }
+
+    /// Number of elements the accelerator has published that have not been
+    /// popped yet.
+    ///
+    /// This is the occupancy of a receiver queue. It reads the hw_tail the
+    /// accelerator advances, so unlike [`num_elems`](Self::num_elems) it is
+    /// accurate without modifying the fifo.
+    pub fn available(&self) -> usize {
+        self.check_invariants(false);
+        self.elems_before(self.hw_tail())
+    }
+
+    /// With the `debug_checks` feature, panics if the pointers are out of
+    /// bounds or the occupancy is inconsistent. Compiles to nothing otherwise.
+    ///
+    /// Every push sizes up the room left with [`num_elems`](Self::num_elems)
+    /// and every pop the elements there with [`available`](Self::available),
+    /// so checking in those two covers all operations. On a `sender` queue
+    /// the hw_tail must also lie between the head and the sw_tail; a
+    /// receiver never moves its sw_tail.
+    #[inline(always)]
+    #[cfg_attr(not(feature = "debug_checks"), allow(unused_variables))]
+    fn check_invariants(&self, sender: bool) {
+        #[cfg(feature = "debug_checks")]
+        {
+            // Each pointer is read once, so the checks agree with each other
+            // even while the accelerator moves its own.
+            let (head, sw_tail, hw_tail) = (self.head(), self.sw_tail(), self.hw_tail());
+            let buffer_size = self.buffer_size();
+            for (name, idx) in [("head", head), ("sw_tail", sw_tail), ("hw_tail", hw_tail)] {
+                assert!(
+                    idx < buffer_size,
+                    "cohort fifo corrupted: {name} {idx} is out of bounds of buffer_size {buffer_size}"
+                );
+            }
+            let distance = |tail: usize| (tail + buffer_size - head) % buffer_size;
+            let (pushed, flushed) = (distance(sw_tail), distance(hw_tail));
+            assert!(
+                flushed <= self.capacity,
+                "cohort fifo corrupted: {flushed} elements between head {head} and hw_tail \
+                 {hw_tail} exceed the capacity of {}",
+                self.capacity
+            );
+            assert!(
+                !sender || (flushed <= pushed && pushed <= self.capacity),
+                "cohort fifo corrupted: hw_tail {hw_tail} is not between head {head} and \
+                 sw_tail {sw_tail}, or they are more than the capacity of {} apart",
+                self.capacity
+            );
+        }
+    }
+
+    /// Moves the index `idx` forward by `by` slots, wrapping at the end of
+    /// the buffer.
+    ///
+    /// Never computes a value past the buffer size, so it cannot overflow.
+    fn advance(&self, idx: usize, by: usize) -> usize {
+        let buffer_size = self.buffer_size();
+        debug_assert!(idx < buffer_size && by <= buffer_size);
+        let to_end = buffer_size - idx;
+        if by >= to_end {
+            by - to_end
+        } else {
+            idx + by
+        }
+    }
+
+    /// Number of elements between the head and `tail`.
+    fn elems_before(&self, tail: usize) -> usize {
+        // The distance wraps over every slot in the buffer, including the
+        // spare one, so this must be buffer_size() and not capacity().
+        let distance = tail + self.buffer_size() - self.head();
+        if self.fast_wrap {
+            distance & (self.buffer_size() - 1)
         } else {
-            return self.capacity() + self.head() - self.sw_tail();
+            distance % self.buffer_size()
         }
     }
 
+    // The head and hw_tail are how the software and the accelerator hand
+    // slots to each other, so loading one acquires whatever the other side
+    // wrote to the buffer before publishing it, and storing one releases
+    // our own buffer accesses. The sw_tail never leaves this side.
+    //
+    // With strict ordering every access is SeqCst instead, for platforms
+    // where acquire and release are not enough.
+
     fn head(&self) -> usize {
-        unsafe { ptr::read_volatile(self.head.0.get()) as usize }
+        self.head.0.load(self.acquire()) as usize
     }
 
     fn sw_tail(&self) -> usize {
-        unsafe { ptr::read_volatile(self.sw_tail.0.get()) as usize }
+        self.sw_tail.0.load(self.relaxed()) as usize
     }
 
     fn hw_tail(&self) -> usize {
-        unsafe { ptr::read_volatile(self.hw_tail.0.get()) as usize }
+        self.hw_tail.0.load(self.acquire()) as usize
     }
 
     fn set_head(&self, head: usize) {
-        fence(Ordering::SeqCst);
-        unsafe {
-            ptr::write_volatile(self.head.0.get(), head as u32);
-        }
-        fence(Ordering::SeqCst);
-
+        self.head.0.store(head as u32, self.release());
     }
 
     fn set_hw_tail(&self, tail: usize) {
-        fence(Ordering::SeqCst);
-        unsafe {
-            ptr::write_volatile(self.hw_tail.0.get(), tail as u32);
-        }
-        fence(Ordering::SeqCst);
-
+        self.hw_tail.0.store(tail as u32, self.release());
     }
 
     fn set_sw_tail(&self, tail: usize) {
-        fence(Ordering::SeqCst);
-        unsafe {
-            ptr::write_volatile(self.sw_tail.0.get(), tail as u32);
-        }
-        fence(Ordering::SeqCst);
+        self.sw_tail.0.store(tail as u32, self.relaxed());
+    }
+
+    fn acquire(&self) -> Ordering {
+        if self.strict_ordering { Ordering::SeqCst } else { Ordering::Acquire }
+    }
 
+    fn release(&self) -> Ordering {
+        if self.strict_ordering { Ordering::SeqCst } else { Ordering::Release }
     }
 
+    fn relaxed(&self) -> Ordering {
+        if self.strict_ordering { Ordering::SeqCst } else { Ordering::Relaxed }
+    }
+
+    // Every access to an element of the buffer goes through slot, so the
+    // index checks live in one place. The accelerator may be working on
+    // other slots at the same time, which is why these hand out raw
+    // pointers and copies rather than references to the whole buffer.
+
+    /// Returns a pointer to slot `idx` of the buffer.
+    ///
+    /// Computing the pointer is always safe; in debug builds an index past
+    /// the end of the buffer panics here instead of becoming an
+    /// out-of-bounds access later.
+    fn slot(&self, idx: usize) -> *mut T {
+        debug_assert!(
+            idx < self.buffer_size(),
+            "slot {idx} is out of bounds of buffer_size {}",
+            self.buffer_size()
+        );
+        self.meta.0.buffer.as_ptr().wrapping_add(idx)
+    }
+
+    /// Copies out the element in slot `idx`, which must be inside the buffer.
+    fn read_slot(&self, idx: usize) -> T {
+        unsafe { self.slot(idx).read() }
+    }
+
+    /// Overwrites the element in slot `idx`, which must be inside the buffer.
+    fn write_slot(&self, idx: usize, elem: T) {
+        unsafe { self.slot(idx).write(elem) }
+    }
+
+    /// The whole buffer, for dumping it.
+    #[cfg(any(feature = "debug", feature = "bytemuck"))]
     fn buffer(&self) -> NonNull<[T]> {
         NonNull::slice_from_raw_parts(self.meta.0.buffer, self.buffer_size())
     }
 
 
-    fn capacity(&self) -> usize {
-        self.buffer_size()-1
+    /// Returns the base address of the ring buffer, for programming DMA
+    /// descriptors or a userspace driver.
+    ///
+    /// The address is 128-byte aligned and stays valid for as long as the
+    /// fifo exists, as its buffer never moves.
+    pub fn buffer_ptr(&self) -> *const T {
+        self.meta.0.buffer.as_ptr()
+    }
+
+    /// Number of element slots in the ring buffer.
+    ///
+    /// This is one more than the capacity, or the next power of two above it
+    /// for a fifo made with [`new_fast_wrap_in`](Self::new_fast_wrap_in).
+    pub fn buffer_len(&self) -> usize {
+        self.buffer_size()
+    }
+
+    /// Element size in bytes as handed to the accelerator, for checking
+    /// against what the kernel programmed.
+    pub fn elem_size(&self) -> u32 {
+        // Meta is packed, so its fields may be misaligned and taking a
+        // reference to one is undefined behaviour. Read through a raw
+        // pointer that makes no alignment assumption instead.
+        unsafe { ptr::addr_of!(self.meta.0._elem_size).read_unaligned() }
+    }
+
+    /// Buffer size in slots as handed to the accelerator; the same as
+    /// [`buffer_len`](Self::buffer_len), read straight from the
+    /// hardware-visible field.
+    pub fn meta_buffer_size(&self) -> u32 {
+        // See elem_size for why this cannot go through a reference.
+        unsafe { ptr::addr_of!(self.meta.0.buffer_size).read_unaligned() }
+    }
+
+    /// Number of bytes the buffer takes up, rounded up to its 128-byte alignment.
+    pub fn capacity_in_bytes(&self) -> usize {
+        Self::layout(self.buffer_size())
+            .expect("the layout was checked when the fifo was created")
+            .pad_to_align()
+            .size()
+    }
+
+    /// Number of elements the fifo can hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of elements pushed before the hw_tail is advanced.
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// The settings a [`CohortBuilder`](crate::CohortBuilder) would need to
+    /// make a fifo like this one: spins before yielding, strict ordering
+    /// and fast wrap.
+    #[cfg(feature = "std")]
+    pub(crate) fn tuning(&self) -> (u32, bool, bool) {
+        (self.spins_before_yield, self.strict_ordering, self.fast_wrap)
+    }
+
+    /// Reads the queue pointers, each with acquire ordering, without
+    /// modifying the fifo.
+    pub fn snapshot(&self) -> FifoSnapshot {
+        FifoSnapshot {
+            head: self.head(),
+            sw_tail: self.sw_tail.0.load(self.acquire()) as usize,
+            hw_tail: self.hw_tail(),
+            capacity: self.capacity(),
+        }
     }
 }
 
-unsafe impl<T: Copy + std::fmt::Debug> Send for CohortFifo<T> {}
-unsafe impl<T: Copy + std::fmt::Debug> Sync for CohortFifo<T>{}
+#[cfg(any(test, feature = "std"))]
+impl<T: Copy + core::fmt::Debug, A: GlobalAlloc> CohortFifo<T, A> {
+    /// Prints the head, sw_tail and hw_tail of the queue.
+    ///
+    /// With the `debug` feature the raw contents of the buffer are printed too.
+    pub fn print_queue(&self){
+        println!("{:?}", self);
+        #[cfg(feature = "debug")]
+        unsafe{ println!("{:?}", self.buffer().as_ref())};
+    }
+}
+
+#[cfg(feature = "bytemuck")]
+impl<T: bytemuck::Pod, A: GlobalAlloc> CohortFifo<T, A> {
+    /// Prints the head, sw_tail and hw_tail of the queue followed by the
+    /// contents of the buffer in hex.
+    pub fn print_queue_hex(&self) {
+        println!("{:?}", self);
+        unsafe { println!("{}", crate::pod::hex(self.buffer().as_ref())) };
+    }
+}
+
+impl<T: Copy, A: GlobalAlloc> core::fmt::Debug for CohortFifo<T, A> {
+    /// Shows the queue pointers without the buffer contents. Only reads the
+    /// pointers, so formatting never modifies the fifo.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CohortFifo")
+            .field("head", &self.head())
+            .field("sw_tail", &self.sw_tail())
+            .field("hw_tail", &self.hw_tail())
+            .field("capacity", &self.capacity())
+            .field("batch_size", &self.batch_size)
+            .finish()
+    }
+}
+
+impl<T: Copy, A: GlobalAlloc> core::fmt::Display for CohortFifo<T, A> {
+    /// Writes the queue pointers and capacity on one line, without
+    /// allocating, e.g. `head 1, sw_tail 5, hw_tail 4, capacity 8`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "head {}, sw_tail {}, hw_tail {}, capacity {}",
+            self.head(),
+            self.sw_tail(),
+            self.hw_tail(),
+            self.capacity()
+        )
+    }
+}
+
+/// Accelerator-side operations used to drive a fifo from tests and the
+/// mock accelerator.
+#[cfg(any(test, feature = "mock"))]
+impl<T: Copy, A: GlobalAlloc> CohortFifo<T, A> {
+    /// Plays the accelerator on a receiver queue: writes `elem` at the
+    /// hw_tail and publishes it.
+    pub(crate) fn accel_push(&self, elem: T) -> Result<()> {
+        if self.accel_is_full() {
+            return Err(Error::Full);
+        }
+        let hw_tail = self.hw_tail();
+        self.write_slot(hw_tail, elem);
+        self.set_hw_tail(self.advance(hw_tail, 1));
+        Ok(())
+    }
 
-impl<T: Copy + std::fmt::Debug> Drop for CohortFifo<T> {
+    /// True if the accelerator has filled a receiver queue.
+    pub(crate) fn accel_is_full(&self) -> bool {
+        self.advance(self.hw_tail(), 1) == self.head()
+    }
+
+    /// Plays the accelerator on a sender queue: consumes one element that
+    /// has been published through the hw_tail.
+    pub(crate) fn accel_pop(&self) -> Option<T> {
+        let head = self.head();
+        if head == self.hw_tail() {
+            return None;
+        }
+        let elem = self.read_slot(head);
+        self.set_head(self.advance(head, 1));
+        Some(elem)
+    }
+}
+
+unsafe impl<T: Copy, A: GlobalAlloc + Send> Send for CohortFifo<T, A> {}
+unsafe impl<T: Copy, A: GlobalAlloc + Sync> Sync for CohortFifo<T, A>{}
+
+impl<T: Copy, A: GlobalAlloc> Drop for CohortFifo<T, A> {
     fn drop(&mut self) {
-        let layout = Layout::array::<T>(self.buffer_size()).unwrap();
-        let aligned = layout.align_to(128).unwrap();
-        unsafe { dealloc(self.meta.0.buffer.cast().as_ptr(), aligned) };
+        if !self.owns_buffer {
+            return;
+        }
+        let layout = Self::layout(self.buffer_size())
+            .expect("the layout was checked when the fifo was created");
+        unsafe { self.alloc.dealloc(self.meta.0.buffer.cast().as_ptr(), layout) };
     }
 }
 
@@ -234,74 +1053,935 @@ impl<T: Copy + std::fmt::Debug> Drop for CohortFifo<T> {
 mod tests {
     use std::thread;
 
-    use super::CohortFifo;
+    use core::alloc::{GlobalAlloc, Layout};
+    use core::cell::{Cell, UnsafeCell};
+    use core::mem;
+    use core::ptr::NonNull;
+
+    use super::{validate, CohortFifo, FifoSnapshot};
+    use crate::error::Error;
+    use crate::util::Aligned;
+
+    /// A fixed region handed out whole, like a statically reserved
+    /// buffer on an embedded target.
+    struct Arena {
+        region: UnsafeCell<Aligned<[u8; 1024]>>,
+        allocated: Cell<bool>,
+        freed: Cell<bool>,
+    }
+
+    unsafe impl GlobalAlloc for &Arena {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            assert!(layout.size() <= 1024 && layout.align() <= 128);
+            assert!(!self.allocated.replace(true));
+            self.region.get().cast()
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+            assert_eq!(ptr, self.region.get().cast());
+            self.freed.set(true);
+        }
+    }
+
+    #[test]
+    fn new_in_uses_custom_allocator() {
+        let arena = Arena {
+            region: UnsafeCell::new(Aligned([0xAA; 1024])),
+            allocated: Cell::new(false),
+            freed: Cell::new(false),
+        };
+
+        let spsc = CohortFifo::<u64, _>::new_in(4, 2, &arena).unwrap();
+        assert!(arena.allocated.get());
+        // The buffer is zeroed even though the region was not.
+        let region = unsafe { &(*arena.region.get()).0 };
+        assert!(region[..5 * 8].iter().all(|&b| b == 0));
+        spsc.push_one(&7);
+        spsc.flush();
+        assert_eq!(spsc.accel_pop(), Some(7));
+        spsc.accel_push(9).unwrap();
+        assert_eq!(spsc.try_take().unwrap(), 9);
+
+        drop(spsc);
+        assert!(arena.freed.get());
+    }
+
+    /// An allocator that is always out of memory.
+    struct Exhausted;
+
+    unsafe impl GlobalAlloc for Exhausted {
+        unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
+            core::ptr::null_mut()
+        }
+
+        unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+            unreachable!("nothing was allocated");
+        }
+    }
+
+    #[test]
+    fn allocation_failure_is_an_error() {
+        let bytes = CohortFifo::<u64>::layout(1025).unwrap().size();
+        assert!(matches!(
+            CohortFifo::<u64, _>::new_in(1024, 2, Exhausted),
+            Err(Error::AllocationFailed { bytes: b }) if b == bytes
+        ));
+        assert!(matches!(
+            CohortFifo::<u64, _>::new_fast_wrap_in(1024, 2, Exhausted),
+            Err(Error::AllocationFailed { bytes: 16384 })
+        ));
+    }
+
+    #[test]
+    fn oversized_buffer_is_an_error() {
+        // A million slots of this overflow the address space.
+        type Huge = [u64; 1 << 40];
+        const CAPACITY: usize = 1 << 20;
+        assert!(matches!(
+            CohortFifo::<Huge>::new(CAPACITY, 2),
+            Err(Error::CapacityTooLarge)
+        ));
+        assert!(matches!(
+            CohortFifo::<Huge>::is_valid_config(CAPACITY, 2),
+            Err(Error::CapacityTooLarge)
+        ));
+        let mut region = Aligned([0_u64; 16]);
+        let region = NonNull::from(&mut region).cast();
+        assert!(matches!(
+            unsafe { CohortFifo::<Huge>::from_mapped(region, CAPACITY, 2) },
+            Err(Error::CapacityTooLarge)
+        ));
+    }
+
+    #[test]
+    fn advance_wraps_at_end_of_buffer() {
+        // A capacity of 4 gives a buffer of 5 slots.
+        let spsc = CohortFifo::<u64>::new(4, 2).unwrap();
+        assert_eq!(spsc.advance(0, 1), 1);
+        assert_eq!(spsc.advance(3, 1), 4);
+        assert_eq!(spsc.advance(4, 1), 0);
+        assert_eq!(spsc.advance(4, 2), 1);
+        assert_eq!(spsc.advance(3, 2), 0);
+        assert_eq!(spsc.advance(2, 0), 2);
+        assert_eq!(spsc.advance(2, 5), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn advance_rejects_more_than_a_lap() {
+        let spsc = CohortFifo::<u64>::new(4, 2).unwrap();
+        spsc.advance(0, 6);
+    }
+
+    #[test]
+    #[cfg(feature = "debug_checks")]
+    #[should_panic(expected = "head 9 is out of bounds of buffer_size 9")]
+    fn debug_checks_catch_corrupt_head() {
+        let spsc = CohortFifo::<u64>::new(8, 2).unwrap();
+        spsc.push(&1, &2);
+        // A stray write from the accelerator side.
+        spsc.set_head(9);
+        let _ = spsc.try_push(&3, &4);
+    }
+
+    #[test]
+    #[cfg(feature = "debug_checks")]
+    #[should_panic(expected = "hw_tail 4 is not between head 0 and sw_tail 2")]
+    fn debug_checks_catch_hw_tail_past_sw_tail() {
+        let spsc = CohortFifo::<u64>::new(8, 2).unwrap();
+        spsc.push(&1, &2);
+        spsc.set_hw_tail(4);
+        let _ = spsc.try_push(&3, &4);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "slot 9 is out of bounds of buffer_size 9")]
+    fn out_of_range_slot_panics() {
+        let spsc = CohortFifo::<u64>::new(8, 2).unwrap();
+        spsc.write_slot(9, 1);
+    }
+
+    #[test]
+    fn mapped_fifos_share_buffer() {
+        let layout = CohortFifo::<u64>::layout(9).unwrap();
+        let region = NonNull::new(unsafe { alloc::alloc::alloc_zeroed(layout) }).unwrap();
+
+        let producer = unsafe { CohortFifo::<u64>::from_mapped(region, 8, 2) }.unwrap();
+        let consumer = unsafe { CohortFifo::<u64>::from_mapped(region, 8, 2) }.unwrap();
+        producer.push(&3, &4);
+        // Stand in for whoever shares the tail between the two mappings.
+        consumer.set_hw_tail(producer.hw_tail());
+
+        let (mut a, mut b) = (0, 0);
+        consumer.try_pop(&mut a, &mut b).unwrap();
+        assert_eq!((a, b), (3, 4));
+
+        // Neither fifo frees the region.
+        drop(producer);
+        drop(consumer);
+        unsafe { alloc::alloc::dealloc(region.as_ptr(), layout) };
+    }
+
+    #[test]
+    fn from_mapped_rejects_misaligned_buffer() {
+        let mut region = Aligned([0_u64; 32]);
+        let misaligned = NonNull::from(&mut region.0[1]).cast();
+        assert!(matches!(
+            unsafe { CohortFifo::<u64>::from_mapped(misaligned, 8, 2) },
+            Err(Error::MisalignedBuffer)
+        ));
+    }
+
+    #[test]
+    fn strict_ordering_round_trips() {
+        let spsc = CohortFifo::<u64>::new(4, 2).unwrap().with_strict_ordering(true);
+        spsc.push(&1, &2);
+        assert_eq!(spsc.accel_pop(), Some(1));
+        assert_eq!(spsc.accel_pop(), Some(2));
+        spsc.accel_push(3).unwrap();
+        assert_eq!(spsc.try_take().unwrap(), 3);
+    }
+
+    #[test]
+    fn strided_round_trips() {
+        let sender = CohortFifo::<u64>::new(8, 4).unwrap();
+        sender.push_n(&[1]);
+        sender.push_n(&[2, 3, 4, 5]);
+        assert!(matches!(sender.try_push_n(&[6, 7, 8, 9]), Err(Error::Full)));
+        for n in 1..=5 {
+            assert_eq!(sender.accel_pop(), Some(n));
+        }
+
+        let receiver = CohortFifo::<u64>::new(8, 4).unwrap();
+        for n in 1..=3 {
+            receiver.accel_push(n).unwrap();
+        }
+        // Only a whole transaction is popped.
+        assert!(matches!(receiver.try_pop_n::<4>(), Err(Error::PartialElement)));
+        assert_eq!(receiver.pop_n(), [1]);
+        for n in 4..=5 {
+            receiver.accel_push(n).unwrap();
+        }
+        assert_eq!(receiver.try_pop_n().unwrap(), [2, 3, 4, 5]);
+        assert!(matches!(receiver.try_pop_n::<1>(), Err(Error::Empty)));
+    }
+
+    #[test]
+    fn display_shows_pointers() {
+        let spsc = CohortFifo::<u64>::new(8, 4).unwrap();
+        spsc.push(&1, &2);
+        spsc.push(&3, &4);
+        for _ in 0..3 {
+            spsc.accel_pop();
+        }
+        spsc.push_one(&5);
+        assert_eq!(spsc.to_string(), "head 3, sw_tail 5, hw_tail 4, capacity 8");
+    }
+
+    #[test]
+    fn fast_wrap_matches_default() {
+        let senders = [
+            CohortFifo::<u64>::new(6, 2).unwrap(),
+            CohortFifo::new_fast_wrap(6, 2).unwrap(),
+        ];
+        let receivers = [
+            CohortFifo::<u64>::new(6, 2).unwrap(),
+            CohortFifo::new_fast_wrap(6, 2).unwrap(),
+        ];
+        assert_eq!((senders[1].capacity(), senders[1].buffer_len()), (6, 8));
+
+        // Run the same mix of operations on both for long enough that the
+        // indices wrap many times, at different points in the two buffers.
+        let mut seed = 1u32;
+        for step in 0..1000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let results = [0, 1].map(|i| match seed >> 16 & 3 {
+                0 => senders[i].try_push(&step, &!step).is_ok() as u64,
+                1 => senders[i].accel_pop().unwrap_or(u64::MAX),
+                2 if receivers[i].available() < 6 => receivers[i].accel_push(step).is_ok() as u64,
+                _ => {
+                    let (mut a, mut b) = (0, 0);
+                    match receivers[i].try_pop(&mut a, &mut b) {
+                        Ok(()) => a ^ b,
+                        Err(_) => u64::MAX,
+                    }
+                }
+            });
+            assert_eq!(results[0], results[1], "step {step}");
+            assert_eq!(senders[0].num_elems(), senders[1].num_elems());
+            assert_eq!(receivers[0].available(), receivers[1].available());
+        }
+    }
+
+    #[test]
+    fn min_valid_capacity_passes_new() {
+        for batch_size in (2..=64).step_by(2) {
+            let capacity = CohortFifo::<u64>::min_valid_capacity(batch_size);
+            assert_eq!(capacity, batch_size);
+            assert!(CohortFifo::<u64>::is_valid_config(capacity, batch_size).is_ok());
+            assert!(CohortFifo::<u64>::new(capacity, batch_size).is_ok());
+        }
+        assert_eq!(CohortFifo::<u64>::min_valid_capacity(0), 2);
+        assert_eq!(CohortFifo::<u64>::min_valid_capacity(7), 8);
+
+        for (capacity, batch_size) in [(8, 0), (8, 3), (4, 8), (9, 8), (8, 8)] {
+            assert_eq!(
+                CohortFifo::<u64>::is_valid_config(capacity, batch_size),
+                CohortFifo::<u64>::new(capacity, batch_size).map(drop)
+            );
+        }
+        assert_eq!(
+            CohortFifo::<[u8; 4]>::is_valid_config(8, 2),
+            Err(Error::UnsupportedElementSize(4))
+        );
+    }
+
+    #[test]
+    fn with_capacity_rounded_picks_valid_capacity() {
+        let (fifo, capacity) = CohortFifo::<u64>::with_capacity_rounded(7, 4).unwrap();
+        assert_eq!(capacity, 8);
+        assert_eq!(fifo.capacity(), 8);
+
+        let (_, capacity) = CohortFifo::<u64>::with_capacity_rounded(1, 4).unwrap();
+        assert_eq!(capacity, 4);
+        assert!(matches!(
+            CohortFifo::<u64>::with_capacity_rounded(7, 3),
+            Err(Error::BatchSizeNotEven)
+        ));
+    }
+
+    #[test]
+    fn layout_matches_accelerator_abi() {
+        type Fifo = CohortFifo<[u8; 8]>;
+        let ptr_size = mem::size_of::<*const u8>();
+
+        // Each hardware-visible pointer starts its own 128-byte line.
+        assert_eq!(mem::offset_of!(Fifo, head), 0);
+        assert_eq!(mem::offset_of!(Fifo, meta.0.buffer), 128);
+        assert_eq!(mem::offset_of!(Fifo, meta.0._elem_size), 128 + ptr_size);
+        assert_eq!(mem::offset_of!(Fifo, meta.0.buffer_size), 128 + ptr_size + 4);
+        assert_eq!(mem::offset_of!(Fifo, hw_tail), 256);
+
+        // The software-only fields start past the line holding hw_tail.
+        assert!(mem::offset_of!(Fifo, batch_size) >= 384);
+        assert!(mem::offset_of!(Fifo, sw_tail) >= 384);
+        assert_eq!(mem::align_of::<Fifo>(), 128);
+        assert_eq!(mem::size_of::<Fifo>(), 6 * 128);
+    }
+
+    #[test]
+    fn meta_fields_read_back() {
+        let fifo = CohortFifo::<[u8; 24]>::new(8, 2).unwrap();
+        assert_eq!(fifo.elem_size() as usize, mem::size_of::<[u8; 24]>());
+        assert_eq!(fifo.meta_buffer_size(), 9);
+
+        let fifo = CohortFifo::<u64>::new_fast_wrap(8, 2).unwrap();
+        assert_eq!(fifo.elem_size() as usize, mem::size_of::<u64>());
+        assert_eq!(fifo.meta_buffer_size() as usize, fifo.buffer_len());
+    }
+
+    #[test]
+    fn snapshot_tracks_pushes() {
+        let fifo = CohortFifo::<u64>::new(16, 16).unwrap();
+        let before = fifo.snapshot();
+        for n in 0..3 {
+            fifo.try_push(&n, &n).unwrap();
+        }
+        let after = fifo.snapshot();
+        assert_eq!(after.sw_tail, before.sw_tail + 2 * 3);
+        assert_eq!(
+            after,
+            FifoSnapshot {
+                head: 0,
+                sw_tail: 6,
+                hw_tail: 0,
+                capacity: 16,
+            }
+        );
+        // Taking a snapshot leaves the fifo untouched.
+        assert_eq!(fifo.snapshot(), after);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn snapshot_round_trips_through_json() {
+        let fifo = CohortFifo::<u64>::new(16, 4).unwrap();
+        fifo.push(&1, &2);
+        let snapshot = fifo.snapshot();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(json, r#"{"head":0,"sw_tail":2,"hw_tail":0,"capacity":16}"#);
+        assert_eq!(serde_json::from_str::<FifoSnapshot>(&json).unwrap(), snapshot);
+    }
+
+    #[test]
+    #[cfg(feature = "prefetch")]
+    fn prefetch_keeps_pops_intact() {
+        let spsc = CohortFifo::<u64>::new(6, 2).unwrap();
+        // Go round the buffer a few times so the prefetch wraps too.
+        for n in 0..20 {
+            spsc.push(&n, &!n);
+            let (mut a, mut b) = (0, 0);
+            spsc.try_pop(&mut a, &mut b).unwrap();
+            assert_eq!((a, b), (n, !n));
+        }
+        assert_eq!(spsc.try_pop_n::<2>(), Err(Error::Empty));
+    }
+
+    #[test]
+    fn buffer_ptr_is_aligned() {
+        let spsc = CohortFifo::<u64>::new(8, 2).unwrap();
+        assert!(!spsc.buffer_ptr().is_null());
+        assert!(spsc.buffer_ptr().addr().is_multiple_of(128));
+        assert_eq!(spsc.buffer_len(), 9);
+    }
+
+    #[test]
+    fn capacity_in_bytes_rounds_to_alignment() {
+        let spsc = CohortFifo::<[u8; 8]>::new(64, 8).unwrap();
+        // 65 slots of 8 bytes, padded to a multiple of 128.
+        assert_eq!(spsc.capacity_in_bytes(), 640);
+    }
 
     #[test]
     fn initializes_empty() {
-        let spsc = CohortFifo::<[u8; 16]>::new(10).unwrap();
+        let spsc = CohortFifo::<[u8; 16]>::new(10, 2).unwrap();
         assert!(spsc.is_empty());
     }
 
+    #[test]
+    fn new_rejects_invalid_config() {
+        assert!(matches!(CohortFifo::<u64>::new(10, 0), Err(Error::BatchSizeTooSmall)));
+        assert!(matches!(CohortFifo::<u64>::new(10, 3), Err(Error::BatchSizeNotEven)));
+        assert!(matches!(CohortFifo::<u64>::new(4, 6), Err(Error::CapacityLessThanBatchSize)));
+        assert!(matches!(CohortFifo::<u64>::new(11, 2), Err(Error::CapacityNotEven(11))));
+    }
+
+    #[test]
+    fn capacity_must_fit_buffer_size() {
+        let largest = u32::MAX as usize - 1;
+        assert!(validate(largest, 2).is_ok());
+        assert!(matches!(validate(largest + 2, 2), Err(Error::CapacityTooLarge)));
+        assert!(matches!(
+            CohortFifo::<u64>::new(largest + 2, 2),
+            Err(Error::CapacityTooLarge)
+        ));
+    }
+
+    #[test]
+    fn new_rejects_partial_words() {
+        assert!(matches!(
+            CohortFifo::<[u8; 7]>::new(8, 2),
+            Err(Error::UnsupportedElementSize(7))
+        ));
+        assert!(matches!(
+            CohortFifo::<()>::new(8, 2),
+            Err(Error::UnsupportedElementSize(0))
+        ));
+        assert!(CohortFifo::<[u8; 8]>::new(8, 2).is_ok());
+        assert!(CohortFifo::<[u8; 16]>::new(8, 2).is_ok());
+    }
+
     #[test]
     fn test_filling_up_and_test_extra_push_and_test_emptying_and_test_extra_pop(){
-        let spsc = CohortFifo::<[u8; 16]>::new(10).unwrap();
+        let spsc = CohortFifo::<[u8; 16]>::new(10, 2).unwrap();
 
         for n in 0..10 {
             let val: [u8; 16] = [n; 16];
-            spsc.push(&val);
+            spsc.push_one(&val);
         }
 
         spsc.print_queue();
         assert!(spsc.is_full());
-        assert!(spsc.try_push(&[11; 16]).is_err());
+        assert!(spsc.try_push_one(&[11; 16]).is_err());
         assert!(spsc.is_full());
 
         for n in 0..5 {
-            let mut val = [0;16];
-            spsc.pop(&mut val);
-            assert_eq!(val, [n;16]);
+            assert_eq!(spsc.accel_pop(), Some([n;16]));
         }
 
         for n in 0..5 {
-            spsc.push(&mut [n;16]);
+            spsc.push_one(&[n;16]);
         }
 
         for n in 5..10 {
-            let mut val = [0;16];
-            spsc.pop(&mut val);
-            assert!(val == [n;16]);
+            assert_eq!(spsc.accel_pop(), Some([n;16]));
+        }
+
+        for n in 0..5 {
+            assert_eq!(spsc.accel_pop(), Some([n;16]));
+        }
+        assert!(spsc.is_empty());
+        assert!(spsc.accel_pop().is_none());
+    }
+
+    #[test]
+    fn paired_push_needs_room_for_both() {
+        let spsc = CohortFifo::<u64>::new(10, 2).unwrap();
+
+        for n in 0..9 {
+            spsc.push_one(&n);
+        }
+        assert_eq!(spsc.num_elems(), 9);
+        assert!(spsc.try_push(&9, &10).is_err());
+        assert_eq!(spsc.num_elems(), 9);
+        assert!(spsc.try_push_one(&9).is_ok());
+        assert!(spsc.is_full());
+    }
+
+    #[test]
+    fn odd_count_on_receiver() {
+        let spsc = CohortFifo::<u64>::new(10, 2).unwrap();
+        for n in 0..3 {
+            spsc.accel_push(n).unwrap();
+        }
+
+        let (mut a, mut b) = (0, 0);
+        assert!(spsc.try_pop(&mut a, &mut b).is_ok());
+        assert_eq!((a, b), (0, 1));
+
+        // A single trailing element is not enough for a pair...
+        assert!(spsc.try_pop(&mut a, &mut b).is_err());
+        assert_eq!(spsc.available(), 1);
+
+        // ...but can still be taken on its own.
+        assert!(spsc.try_pop_one(&mut a).is_ok());
+        assert_eq!(a, 2);
+        assert!(spsc.try_pop_one(&mut a).is_err());
+        assert_eq!(spsc.available(), 0);
+    }
+
+    #[test]
+    fn odd_count_flushes_at_batch_size() {
+        let spsc = CohortFifo::<u64>::new(10, 4).unwrap();
+        for n in 0..3 {
+            spsc.push_one(&n);
+        }
+        assert_eq!(spsc.hw_tail(), 0);
+
+        spsc.push_one(&3);
+        assert_eq!(spsc.hw_tail(), 4);
+
+        spsc.push_one(&4);
+        assert_eq!(spsc.hw_tail(), 5);
+    }
+
+    #[test]
+    fn flushes_at_batch_boundary() {
+        let spsc = CohortFifo::<u64>::new(16, 4).unwrap();
+        spsc.push(&0, &1);
+        spsc.push_one(&2);
+        assert_eq!(spsc.hw_tail(), 0);
+        spsc.push_one(&3);
+        assert_eq!(spsc.hw_tail(), 4);
+
+        // Once the accelerator has consumed the batch, the next one is
+        // counted from the head again.
+        for _ in 0..4 {
+            spsc.accel_pop().unwrap();
+        }
+        spsc.try_push_slice(&[4, 5, 6]);
+        assert_eq!(spsc.hw_tail(), 4);
+        spsc.push_one(&7);
+        assert_eq!(spsc.hw_tail(), 8);
+    }
+
+    #[test]
+    fn manual_flush_keeps_counting_from_the_head() {
+        let spsc = CohortFifo::<u64>::new(16, 4).unwrap();
+        spsc.try_push_slice(&[0, 1, 2]);
+        spsc.flush();
+        assert_eq!(spsc.hw_tail(), 3);
+
+        // The flushed elements have not been consumed, so they still count
+        // towards the batch.
+        spsc.push_one(&3);
+        assert_eq!(spsc.hw_tail(), 4);
+        spsc.push_one(&4);
+        assert_eq!(spsc.hw_tail(), 5);
+
+        for _ in 0..5 {
+            spsc.accel_pop().unwrap();
+        }
+        spsc.try_push_slice(&[5, 6, 7]);
+        assert_eq!(spsc.hw_tail(), 5);
+        spsc.push_one(&8);
+        assert_eq!(spsc.hw_tail(), 9);
+    }
+
+    #[test]
+    fn full_sender_and_empty_receiver_report_variant() {
+        let sender = CohortFifo::<u64>::new(4, 2).unwrap();
+        for n in 0..4 {
+            sender.push_one(&n);
+        }
+        assert!(matches!(sender.try_push_one(&4), Err(Error::Full)));
+        assert!(matches!(sender.try_push(&4, &5), Err(Error::Full)));
+
+        let receiver = CohortFifo::<u64>::new(4, 2).unwrap();
+        let (mut a, mut b) = (0, 0);
+        assert!(matches!(receiver.try_pop_one(&mut a), Err(Error::Empty)));
+        assert!(matches!(receiver.try_pop(&mut a, &mut b), Err(Error::Empty)));
+
+        // One element waiting for its pair is told apart from nothing at all.
+        receiver.accel_push(1).unwrap();
+        assert!(matches!(receiver.try_pop(&mut a, &mut b), Err(Error::PartialElement)));
+        receiver.accel_push(2).unwrap();
+        receiver.try_pop(&mut a, &mut b).unwrap();
+        assert_eq!((a, b), (1, 2));
+    }
+
+    #[test]
+    fn push_slice_wraps_around() {
+        let spsc = CohortFifo::<u64>::new(8, 2).unwrap();
+        for n in (0..6).step_by(2) {
+            spsc.push(&n, &(n + 1));
+            assert_eq!(spsc.accel_pop(), Some(n));
+            assert_eq!(spsc.accel_pop(), Some(n + 1));
         }
 
+        // The tail now sits three slots before the end of the buffer.
+        let data: Vec<u64> = (10..18).collect();
+        assert_eq!(spsc.try_push_slice(&data), 8);
+        assert!(spsc.is_full());
+        assert_eq!(spsc.sw_tail(), 5);
+        assert_eq!(spsc.hw_tail(), 5);
+
+        for n in 10..18 {
+            assert_eq!(spsc.accel_pop(), Some(n));
+        }
+        assert!(spsc.is_empty());
+    }
+
+    #[test]
+    fn try_push_slice_fills_what_it_can() {
+        let spsc = CohortFifo::<u64>::new(8, 2).unwrap();
         for n in 0..5 {
-            let mut val = [0;16];
-            spsc.pop(&mut val);
-            assert!(val == [n;16]);
+            spsc.push_one(&n);
+        }
+
+        let data: Vec<u64> = (5..10).collect();
+        assert_eq!(spsc.try_push_slice(&data), 3);
+        assert!(spsc.is_full());
+        assert_eq!(spsc.try_push_slice(&data[3..]), 0);
+
+        for n in 0..8 {
+            assert_eq!(spsc.accel_pop(), Some(n));
+        }
+        assert_eq!(spsc.try_push_slice(&data[3..]), 2);
+        assert_eq!(spsc.accel_pop(), Some(8));
+        assert_eq!(spsc.accel_pop(), Some(9));
+    }
+
+    #[test]
+    fn available_tracks_hw_tail_without_popping() {
+        let spsc = CohortFifo::<u64>::new(4, 2).unwrap();
+        assert_eq!(spsc.available(), 0);
+        for n in 0..3 {
+            spsc.accel_push(n).unwrap();
+            assert_eq!(spsc.available(), n as usize + 1);
+        }
+        // Querying never touches the sw_tail or the head.
+        assert_eq!((spsc.head(), spsc.sw_tail()), (0, 0));
+
+        // Wrap the hw_tail around the end of the buffer.
+        let (mut a, mut b) = (0, 0);
+        spsc.try_pop(&mut a, &mut b).unwrap();
+        for n in 3..6 {
+            spsc.accel_push(n).unwrap();
+        }
+        assert_eq!(spsc.available(), 4);
+        assert!(spsc.accel_is_full());
+    }
+
+    #[test]
+    fn peek_leaves_elements_in_place() {
+        let spsc = CohortFifo::<u64>::new(4, 2).unwrap();
+        let (mut a, mut b) = (0, 0);
+        assert!(matches!(spsc.try_peek(&mut a, &mut b), Err(Error::Empty)));
+        spsc.accel_push(1).unwrap();
+        assert!(matches!(
+            spsc.try_peek(&mut a, &mut b),
+            Err(Error::PartialElement)
+        ));
+
+        // Wrap the pair around the end of the buffer.
+        for n in 2..5 {
+            spsc.accel_push(n).unwrap();
+        }
+        spsc.try_pop(&mut a, &mut b).unwrap();
+        spsc.try_pop(&mut a, &mut b).unwrap();
+        spsc.accel_push(5).unwrap();
+        spsc.accel_push(6).unwrap();
+
+        for _ in 0..2 {
+            spsc.try_peek(&mut a, &mut b).unwrap();
+            assert_eq!((a, b), (5, 6));
+        }
+        spsc.try_pop(&mut a, &mut b).unwrap();
+        assert_eq!((a, b), (5, 6));
+        assert_eq!(spsc.available(), 0);
+    }
+
+    #[test]
+    fn pop_slice_drains_in_chunks() {
+        let spsc = CohortFifo::<u64>::new(34, 2).unwrap();
+        for n in 0..33 {
+            spsc.accel_push(n).unwrap();
+        }
+
+        let mut popped = Vec::new();
+        let mut out = [0; 8];
+        loop {
+            let count = spsc.pop_slice(&mut out);
+            if count == 0 {
+                break;
+            }
+            popped.extend_from_slice(&out[..count]);
+        }
+
+        // The odd trailing element is not stranded.
+        assert_eq!(popped, (0..33).collect::<Vec<_>>());
+        assert_eq!(spsc.available(), 0);
+    }
+
+    #[test]
+    fn pop_exact_delivers_whole_frames() {
+        let spsc = CohortFifo::<u64>::new(10, 2).unwrap();
+        for n in 0..6 {
+            spsc.accel_push(n).unwrap();
+        }
+
+        let mut frame = [u64::MAX; 8];
+        assert!(matches!(spsc.try_pop_exact(&mut frame), Err(Error::Empty)));
+        assert_eq!(frame, [u64::MAX; 8]);
+        assert_eq!(spsc.available(), 6);
+
+        for n in 6..8 {
+            spsc.accel_push(n).unwrap();
+        }
+        spsc.try_pop_exact(&mut frame).unwrap();
+        assert_eq!(frame, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(spsc.available(), 0);
+    }
+
+    #[test]
+    fn pop_slice_wraps_around() {
+        let spsc = CohortFifo::<u64>::new(8, 2).unwrap();
+        for n in 0..6 {
+            spsc.accel_push(n).unwrap();
+        }
+        let mut out = [0; 6];
+        assert_eq!(spsc.pop_slice(&mut out), 6);
+
+        for n in 10..18 {
+            spsc.accel_push(n).unwrap();
+        }
+        let mut out = [0; 10];
+        assert_eq!(spsc.pop_slice(&mut out), 8);
+        assert_eq!(&out[..8], &(10..18).collect::<Vec<_>>()[..]);
+        assert_eq!(spsc.available(), 0);
+    }
+
+    #[test]
+    fn num_elems_across_wrap_around() {
+        let spsc = CohortFifo::<u64>::new(8, 2).unwrap();
+        for n in 0..8 {
+            spsc.push_one(&n);
+            assert_eq!(spsc.num_elems(), n as usize + 1);
+        }
+        assert!(spsc.is_full());
+
+        for n in 0..3 {
+            assert_eq!(spsc.accel_pop(), Some(n));
+            assert_eq!(spsc.num_elems(), 7 - n as usize);
+        }
+
+        // The ninth slot is the last one in the buffer, so these wrap the tail to the front.
+        for n in 8..11 {
+            spsc.push_one(&n);
+            assert_eq!(spsc.num_elems(), n as usize - 2);
+        }
+        assert!(spsc.sw_tail() < spsc.head());
+        assert!(spsc.is_full());
+
+        for n in 3..11 {
+            assert_eq!(spsc.accel_pop(), Some(n));
+            assert_eq!(spsc.num_elems(), 10 - n as usize);
         }
         assert!(spsc.is_empty());
-        let mut val = [0;16];
-        assert!(spsc.try_pop(&mut val).is_err());
+    }
+
+    #[test]
+    fn batch_flush_after_wrap_around() {
+        let spsc = CohortFifo::<u64>::new(8, 4).unwrap();
+        for n in 0..6 {
+            spsc.push_one(&n);
+        }
+        for _ in 0..6 {
+            spsc.accel_pop().unwrap();
+        }
+
+        for n in 0..3 {
+            spsc.push_one(&n);
+        }
+        // The tail has wrapped past the end with only three elements queued.
+        assert_eq!(spsc.sw_tail(), 0);
+        assert_eq!(spsc.num_elems(), 3);
+        assert_eq!(spsc.hw_tail(), 6);
+
+        spsc.push_one(&3);
+        assert_eq!(spsc.hw_tail(), 1);
+    }
+
+    #[test]
+    fn pure_spinning_still_pops() {
+        let spsc = CohortFifo::<u64>::new(10, 2)
+            .unwrap()
+            .with_spins_before_yield(u32::MAX);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for n in 0..1000 {
+                    while spsc.accel_push(n).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            });
+            for n in (0..1000).step_by(2) {
+                let (mut a, mut b) = (0, 0);
+                spsc.pop(&mut a, &mut b);
+                assert_eq!((a, b), (n, n + 1));
+            }
+        });
+    }
+
+    #[test]
+    fn flush_publishes_partial_batch() {
+        let spsc = CohortFifo::<u64>::new(16, 8).unwrap();
+        spsc.push(&1, &2);
+        spsc.push_one(&3);
+        assert_eq!(spsc.hw_tail(), 0);
+        assert_eq!(spsc.accel_pop(), None);
+
+        spsc.flush();
+        assert_eq!(spsc.hw_tail(), spsc.sw_tail());
+        for n in 1..4 {
+            assert_eq!(spsc.accel_pop(), Some(n));
+        }
+    }
+
+    #[test]
+    fn push_with_writes_in_place() {
+        let spsc = CohortFifo::<[u8; 64]>::new(4, 2).unwrap();
+        for n in 0..2u8 {
+            spsc.try_push_with(|elem1, elem2| {
+                assert_eq!(*elem1, [0; 64]);
+                elem1[0] = n;
+                elem2[63] = n + 10;
+            })
+            .unwrap();
+        }
+        assert!(matches!(spsc.try_push_with(|_, _| unreachable!()), Err(Error::Full)));
+
+        for n in 0..2u8 {
+            let elem1 = spsc.accel_pop().unwrap();
+            let elem2 = spsc.accel_pop().unwrap();
+            assert_eq!(elem1[0], n);
+            assert!(elem1[1..].iter().all(|&b| b == 0));
+            assert_eq!(elem2[63], n + 10);
+            assert!(elem2[..63].iter().all(|&b| b == 0));
+        }
+    }
+
+    #[test]
+    fn push_with_panic_publishes_nothing() {
+        let spsc = CohortFifo::<u64>::new(4, 2).unwrap();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            spsc.try_push_with(|_, _| panic!("building the element failed"))
+        }));
+        assert!(result.is_err());
+        assert!(spsc.is_empty());
+        assert_eq!(spsc.hw_tail(), 0);
+    }
+
+    #[test]
+    fn reset_empties_the_fifo() {
+        let spsc = CohortFifo::<u64>::new(8, 2).unwrap();
+        for n in 0..8 {
+            spsc.push_one(&n);
+        }
+        spsc.accel_pop().unwrap();
+        assert_eq!(spsc.num_elems(), 7);
+
+        unsafe { spsc.reset() };
+        assert!(spsc.is_empty());
+        assert_eq!(spsc.num_elems(), 0);
+        assert_eq!(spsc.hw_tail(), 0);
+
+        // The buffer is reused from the start.
+        spsc.push(&10, &11);
+        assert_eq!(spsc.accel_pop(), Some(10));
+        assert_eq!(spsc.accel_pop(), Some(11));
     }
 
     #[test]
     fn test_two_threads(){
-        let spsc = CohortFifo::<[u8;16]>::new(10).unwrap();
+        let spsc = CohortFifo::<[u8;16]>::new(10, 2).unwrap();
 
         thread::scope( |s| {
             const THROUGHPUT: u32 = 10_000_000;
-            let handle = s.spawn(|| {
+            s.spawn(|| {
             for i in 0..THROUGHPUT {
-                spsc.push(&[(i%64) as u8;16]);
+                while spsc.accel_push([(i%64) as u8;16]).is_err() {
+                    thread::yield_now();
+                }
             }
         });
 
-        for i in 0..THROUGHPUT {
-            let mut elem =[0;16];
-            spsc.pop(&mut elem);
-            assert_eq!(elem, [(i%64) as u8;16]);
+        for i in (0..THROUGHPUT).step_by(2) {
+            let mut elem1 =[0;16];
+            let mut elem2 =[0;16];
+            spsc.pop(&mut elem1, &mut elem2);
+            assert_eq!(elem1, [(i%64) as u8;16]);
+            assert_eq!(elem2, [((i+1)%64) as u8;16]);
         }
-        assert!(spsc.is_empty());
+        assert_eq!(spsc.available(), 0);
        
     });
 
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn no_lost_updates_towards_accelerator() {
+        // The reverse of test_two_threads: the software pushes and another
+        // thread plays the accelerator draining the sender.
+        const THROUGHPUT: u64 = 10_000_000;
+        let spsc = CohortFifo::<u64>::new(16, 4).unwrap();
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in (0..THROUGHPUT).step_by(2) {
+                    spsc.push(&i, &(i + 1));
+                }
+                // Batches are counted from the head, so when the consumer
+                // keeps up the last pair may not complete one.
+                spsc.flush();
+            });
+
+            for i in 0..THROUGHPUT {
+                loop {
+                    match spsc.accel_pop() {
+                        Some(elem) => {
+                            assert_eq!(elem, i);
+                            break;
+                        }
+                        None => thread::yield_now(),
+                    }
+                }
+            }
+        });
+        assert!(spsc.is_empty());
+    }
+}
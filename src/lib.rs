@@ -8,139 +8,2781 @@
 //! # Examples
 //!
 //! ```no_run
+//! # #[cfg(feature = "std")] {
+//! use cohort::Cohort;
+//!
 //! // SAFETY: No other cohorts are associated with id 0.
-//! let cohort = unsafe { Cohort::register(0, 32) };
+//! let cohort = unsafe { Cohort::<u64>::register(0, 32, 8) }.unwrap();
 //! // Send data to the accelerator.
-//! cohort.push(10);
+//! let producer = cohort.producer().unwrap();
+//! producer.push(&10, &20);
 //! // Get data from the accelerator.
-//! let data = cohort.pop();
+//! let consumer = cohort.consumer().unwrap();
+//! let (mut data1, mut data2) = (0, 0);
+//! consumer.pop(&mut data1, &mut data2);
+//! # }
 //! ```
-#![feature(atomic_from_mut)]
+//!
+//! # `no_std`
+//!
+//! Without the default `std` feature only the queue itself, [`CohortFifo`],
+//! is available. It needs nothing but `alloc`, and its buffer can come from
+//! any [`GlobalAlloc`](core::alloc::GlobalAlloc) with
+//! [`CohortFifo::new_in`]. Registering a cohort with the kernel requires `std`.
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 #![warn(missing_docs)]
-#![feature(ptr_as_uninit)]
 
+extern crate alloc;
+
+#[cfg(feature = "async")]
+mod async_io;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod bytes;
+#[cfg(feature = "std")]
+mod drain;
+pub mod error;
 mod fifo;
+#[cfg(feature = "mock")]
+mod mock;
+#[cfg(feature = "bytemuck")]
+mod pod;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(feature = "std")]
+mod split;
+#[cfg(feature = "std")]
+mod stats;
+#[cfg(feature = "std")]
+mod sys;
+#[cfg(feature = "std")]
+mod token;
 pub(crate) mod util;
 
+#[cfg(feature = "std")]
 use core::marker::PhantomPinned;
+#[cfg(feature = "std")]
+use core::mem;
+#[cfg(feature = "std")]
 use core::pin::Pin;
-use core::sync::atomic::AtomicU64;
+#[cfg(feature = "std")]
+use core::ptr::NonNull;
+#[cfg(feature = "std")]
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(all(feature = "std", target_os = "linux"))]
+use std::os::fd::{AsFd, AsRawFd, OwnedFd, RawFd};
+#[cfg(feature = "std")]
+use std::path::Path;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
 
-use fifo::CohortFifo;
+#[cfg(feature = "std")]
+pub use builder::{CohortBuilder, CohortConfig};
+#[cfg(feature = "std")]
+pub use bytes::{ByteCohort, CohortReader, CohortWriter};
+#[cfg(feature = "std")]
+pub use drain::Drain;
+pub use error::{Error, Result};
+pub use fifo::{CohortFifo, DefaultAlloc, FifoSnapshot};
+#[cfg(feature = "mock")]
+pub use mock::LoopbackAccelerator;
+#[cfg(feature = "std")]
+pub use shared::SharedCohort;
+#[cfg(feature = "std")]
+pub use split::{CohortReceiver, CohortSender};
+#[cfg(feature = "stats")]
+pub use stats::CohortStats;
+#[cfg(feature = "std")]
+pub use token::{Consumer, Producer};
 
-use crate::util::Aligned;
+pub use crate::util::{Aligned, AlignedTo, Alignment, SupportedAlignment};
 
+/// The default backoff handed to the register syscall; see
+/// [`CohortBuilder::backoff`].
+#[cfg(feature = "std")]
 const BACKOFF_COUNTER_VAL: u64 = 240;
 
+/// Number of spins between clock reads in the timed blocking calls.
+#[cfg(feature = "std")]
+const TIMEOUT_CHECK_INTERVAL: u32 = 64;
+
 
 /// a single-producer, single-consumer (SPSC) interface used to communciate with hardware accelerators.
 ///
+/// Elements of type `S` are sent to the accelerator and elements of type `R`
+/// are received from it. `R` defaults to `S` for accelerators that answer in
+/// the same format.
+///
+/// The accelerator moves data in 8-byte words, so both element types must be
+/// a non-zero multiple of 8 bytes in size (e.g. `u64`, `[u8; 8]` or `[u8; 16]`).
+/// Registering with any other size fails with [`Error::UnsupportedElementSize`].
+///
 /// ```no_run
+/// # use cohort::Cohort;
 /// // SAFETY: No other cohorts are associated with id 0.
-/// let cohort = unsafe { Cohort::register(0, 32) };
+/// let cohort = unsafe { Cohort::<u64>::register(0, 32, 8) }.unwrap();
 /// // Send data to the accelerator.
-/// cohort.push(10);
+/// let producer = cohort.producer().unwrap();
+/// producer.push(&10, &20);
 /// // Get data from the accelerator.
-/// let data = cohort.pop();
+/// let consumer = cohort.consumer().unwrap();
+/// let (mut data1, mut data2) = (0, 0);
+/// consumer.pop(&mut data1, &mut data2);
+/// ```
+///
+/// The pushes and pops on the cohort itself are unsafe, since nothing stops
+/// two threads calling them at once on a shared cohort; the [`Producer`] and
+/// [`Consumer`] make the same calls safe.
+///
+/// # Pinning
+///
+/// Registering hands the kernel the addresses of the queue pointers inside
+/// the cohort, and the accelerator keeps reading and writing them there
+/// until the cohort is unregistered. Moving the cohort would leave the
+/// accelerator working on stale memory, so it is only ever handed out as a
+/// `Pin<Box<Cohort>>`: the cohort lives on the heap, moving the box only
+/// moves the pointer to it, and as `Cohort` is neither `Unpin`, `Clone` nor
+/// `Copy` there is no safe way to move or duplicate the pinned value.
+///
+/// ```no_run
+/// # use cohort::Cohort;
+/// let cohort = unsafe { Cohort::<u64>::register(0, 32, 8) }.unwrap();
+/// let registered: *const Cohort<u64> = &*cohort;
+///
+/// // Moving the box leaves the cohort itself where the kernel was told it is.
+/// let moved = cohort;
+/// assert_eq!(&*moved as *const Cohort<u64>, registered);
 /// ```
-pub struct Cohort<T: Copy + std::fmt::Debug> {
+#[cfg(feature = "std")]
+pub struct Cohort<S: Copy, R: Copy = S> {
     _id: u8,
-    sender: CohortFifo<T>,
-    receiver: CohortFifo<T>,
+    sender: CohortFifo<S>,
+    receiver: CohortFifo<R>,
     custom_data: Aligned<AtomicU64>, //TODO: Determine type
+    // The backoff counter handed to the register syscall.
+    backoff: u64,
+    // Whether the kernel accepted the register syscall, and so whether
+    // there is anything to unregister. Set while the syscall is in flight
+    // too, so the cohort is never registered twice.
+    registered: AtomicBool,
+    // Told about failures to unregister when the cohort is dropped.
+    on_unregister_error: fn(u8, Error),
+    // Whether pops are checked against the elements pushed and the count
+    // the accelerator reports, and the number of elements each side has
+    // moved so far.
+    verify: bool,
+    pushed_elems: AtomicU64,
+    popped_elems: AtomicU64,
+    // Empty unless the stats feature is on.
+    stats: stats::Counters,
+    // Whether a Producer or Consumer is currently handed out.
+    producer_taken: AtomicBool,
+    consumer_taken: AtomicBool,
+    // The eventfd handed out by readiness_fd, created on first use.
+    #[cfg(target_os = "linux")]
+    readiness: std::sync::OnceLock<OwnedFd>,
     // Prevents compiler from implementing unpin trait
     _pin: PhantomPinned,
 }
 
-impl<T: Copy + std::fmt::Debug> Cohort<T> {
+#[cfg(feature = "std")]
+impl<S: Copy, R: Copy> Cohort<S, R> {
     /// Registers a cohort with the provided id with the given capacity.
     ///
+    /// See [`CohortBuilder`] for finer control over the configuration.
+    ///
+    /// This ignores whether the kernel accepted the registration, so the
+    /// returned cohort may never be serviced by an accelerator. Use
+    /// [`try_register`](Self::try_register) to find out.
+    ///
     /// # Safety
     ///
     /// The cohort id must not currently be in use.
-    pub unsafe fn register(id: u8, capacity: usize, batch_size: usize) -> Pin<Box<Self>> {
-        let sender = CohortFifo::new(capacity, batch_size).unwrap();
+    pub unsafe fn register(id: u8, capacity: usize, batch_size: usize) -> Result<Pin<Box<Self>>> {
+        let config = CohortBuilder::new()
+            .id(id)
+            .capacity(capacity)
+            .batch_size(batch_size);
+        let cohort = Self::with_config(&config)?;
+        let _ = cohort.cohort_mn_register();
+        Ok(cohort)
+    }
 
-        // Batch size doesn't matter for the receiver because we are not pushing data
-        // onto the receiver queue
-        let receiver = CohortFifo::new(capacity, batch_size).unwrap();
-        let custom_data = Aligned(AtomicU64::new(0));
+    /// Registers a cohort with the provided id and the settings of
+    /// `config`, typically taken from another cohort with
+    /// [`config`](Self::config).
+    ///
+    /// Like [`register`](Self::register), this ignores whether the kernel
+    /// accepted the registration.
+    ///
+    /// # Safety
+    ///
+    /// The cohort id must not currently be in use.
+    pub unsafe fn register_with_config(id: u8, config: &CohortConfig) -> Result<Pin<Box<Self>>> {
+        let cohort = Self::with_config(&CohortBuilder::from(*config).id(id))?;
+        let _ = cohort.cohort_mn_register();
+        Ok(cohort)
+    }
 
-        let cohort = Box::pin(Cohort {
-            _id: id,
-            sender,
-            receiver,
-            custom_data,
-            _pin: PhantomPinned,
-        });
+    /// Allocates a cohort with the provided id and capacity without
+    /// registering it.
+    ///
+    /// Nothing is handed to the kernel until [`activate`](Self::activate) is
+    /// called, so the custom data word can be filled in and the queues
+    /// inspected before the accelerator starts reading them.
+    pub fn new(id: u8, capacity: usize, batch_size: usize) -> Result<Pin<Box<Self>>> {
+        Self::with_config(
+            &CohortBuilder::new()
+                .id(id)
+                .capacity(capacity)
+                .batch_size(batch_size),
+        )
+    }
+
+    /// Registers a cohort made with [`new`](Self::new).
+    ///
+    /// Returns [`Error::RegistrationFailed`] with the errno reported by the
+    /// register syscall if the kernel refused the cohort, after which
+    /// activating can be retried. Does nothing if the cohort is already
+    /// registered.
+    ///
+    /// # Safety
+    ///
+    /// The cohort id must not currently be in use.
+    pub unsafe fn activate(self: Pin<&Self>) -> Result<()> {
+        match self.cohort_mn_register() {
+            Err(Error::AlreadyRegistered) => Ok(()),
+            result => result,
+        }
+    }
 
+    /// Registers a cohort with the provided id with the given capacity, failing
+    /// if the kernel rejects it.
+    ///
+    /// Returns [`Error::RegistrationFailed`] with the errno reported by the
+    /// register syscall if the kernel refused the cohort.
+    ///
+    /// # Safety
+    ///
+    /// The cohort id must not currently be in use.
+    pub unsafe fn try_register(
+        id: u8,
+        capacity: usize,
+        batch_size: usize,
+    ) -> Result<Pin<Box<Self>>> {
         unsafe {
-            libc::syscall(
-                258,
-                &cohort.sender,
-                &cohort.receiver,
-                &(cohort.custom_data.0),
-                BACKOFF_COUNTER_VAL,
-            );
+            CohortBuilder::new()
+                .id(id)
+                .capacity(capacity)
+                .batch_size(batch_size)
+                .build()
+        }
+    }
+
+    /// Registers a cohort like [`try_register`](Self::try_register), retrying
+    /// if the kernel rejects it, e.g. while the id is still being torn down.
+    ///
+    /// The register syscall is issued up to `attempts` times, at least once,
+    /// sleeping for `delay` after each failure. Returns
+    /// [`Error::RegistrationFailed`] with the last errno once every attempt
+    /// has failed.
+    ///
+    /// # Safety
+    ///
+    /// The cohort id must not currently be in use.
+    pub unsafe fn try_register_with_retry(
+        id: u8,
+        capacity: usize,
+        batch_size: usize,
+        attempts: u32,
+        delay: Duration,
+    ) -> Result<Pin<Box<Self>>> {
+        let config = CohortBuilder::new()
+            .id(id)
+            .capacity(capacity)
+            .batch_size(batch_size);
+        let cohort = Self::with_config(&config)?;
+        let mut attempt = 1;
+        loop {
+            match cohort.cohort_mn_register() {
+                Err(Error::RegistrationFailed(_)) if attempt < attempts => {
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                result => return result.map(|()| cohort),
+            }
         }
+    }
+
+    /// Registers one cohort per id in `ids`, all with the same capacity and
+    /// batch size, like [`try_register`](Self::try_register).
+    ///
+    /// Either every cohort is registered or none is: if one fails, the ones
+    /// registered before it are dropped, and so unregistered, before its
+    /// error is returned.
+    ///
+    /// # Safety
+    ///
+    /// None of the ids may currently be in use, and each may appear only once.
+    pub unsafe fn register_many(
+        ids: &[u8],
+        capacity: usize,
+        batch_size: usize,
+    ) -> Result<Vec<Pin<Box<Self>>>> {
+        ids.iter()
+            .map(|&id| unsafe { Self::try_register(id, capacity, batch_size) })
+            .collect()
+    }
+
+    /// Registers a cohort with the accelerator behind a device file such as
+    /// `/dev/cohort3`, instead of a raw id.
+    ///
+    /// The id is read from the digits that end the file name. Like
+    /// [`try_register`](Self::try_register) this fails if the kernel rejects
+    /// the cohort. Fails with [`Error::DeviceNotFound`] if the device cannot
+    /// be opened or its name does not end in an id.
+    ///
+    /// # Safety
+    ///
+    /// The device's cohort must not currently be in use.
+    pub unsafe fn register_device(
+        path: &Path,
+        capacity: usize,
+        batch_size: usize,
+    ) -> Result<Pin<Box<Self>>> {
+        File::open(path).map_err(|_| Error::DeviceNotFound)?;
+        let id = device_id(path).ok_or(Error::DeviceNotFound)?;
+        unsafe { Self::try_register(id, capacity, batch_size) }
+    }
+
+    /// Pins the calling thread to `cpu`, then registers a cohort like
+    /// [`try_register`](Self::try_register).
+    ///
+    /// The calling thread becomes the designated producer and consumer of the
+    /// cohort, so its spins on `push`/`pop` never migrate between caches. The
+    /// affinity is left in place on return and is not restored when the
+    /// cohort is dropped. Fails with [`Error::AffinityFailed`] if the thread
+    /// could not be pinned, in which case nothing is registered.
+    ///
+    /// # Safety
+    ///
+    /// The cohort id must not currently be in use.
+    #[cfg(target_os = "linux")]
+    pub unsafe fn register_on_cpu(
+        id: u8,
+        capacity: usize,
+        batch_size: usize,
+        cpu: usize,
+    ) -> Result<Pin<Box<Self>>> {
+        sys::pin_current_thread(cpu).map_err(Error::AffinityFailed)?;
+        unsafe { Self::try_register(id, capacity, batch_size) }
+    }
+
+    /// Allocates the queues for a cohort described by `config` without registering it.
+    ///
+    /// Fails if the capacity or batch size is invalid.
+    pub(crate) fn with_config(config: &CohortBuilder) -> Result<Pin<Box<Self>>> {
+        // We never push onto the receiver queue, so its batch size is the
+        // output batch size that pop_batch waits for.
+        let batch_size = config.resolved_batch_size()?;
+        let out_batch_size = config.resolved_out_batch_size()?;
+        let (sender, receiver) = if config.fast_wrap {
+            (
+                CohortFifo::new_fast_wrap(config.capacity, batch_size)?,
+                CohortFifo::new_fast_wrap(config.capacity, out_batch_size)?,
+            )
+        } else {
+            (
+                CohortFifo::new(config.capacity, batch_size)?,
+                CohortFifo::new(config.capacity, out_batch_size)?,
+            )
+        };
+        Ok(Self::assemble(config, config.tune(sender), config.tune(receiver)))
+    }
+
+    /// Registers a cohort over queue buffers mapped in from elsewhere, such
+    /// as regions shared with an accelerator driver in another process.
+    ///
+    /// The buffers are used as is and never freed; see
+    /// [`CohortFifo::from_mapped`]. Like [`try_register`](Self::try_register)
+    /// this fails if the kernel rejects the cohort.
+    ///
+    /// # Safety
+    ///
+    /// The cohort id must not currently be in use. `sender` and `receiver`
+    /// must each point to at least `capacity + 1` writable elements of `S`
+    /// and `R` respectively that stay mapped for as long as the cohort exists.
+    pub unsafe fn from_mapped(
+        id: u8,
+        sender: NonNull<u8>,
+        receiver: NonNull<u8>,
+        capacity: usize,
+        batch_size: usize,
+    ) -> Result<Pin<Box<Self>>> {
+        let config = CohortBuilder::new()
+            .id(id)
+            .capacity(capacity)
+            .batch_size(batch_size);
+        let (sender, receiver) = unsafe {
+            (
+                CohortFifo::from_mapped(sender, capacity, batch_size)?,
+                CohortFifo::from_mapped(receiver, capacity, batch_size)?,
+            )
+        };
+        let cohort = Self::assemble(&config, config.tune(sender), config.tune(receiver));
+        cohort.cohort_mn_register()?;
+        Ok(cohort)
+    }
+
+    /// Registers a cohort over queues the caller has built, e.g. with
+    /// [`CohortFifo::from_mapped`] over hugepages, for full control over
+    /// where the buffers live.
+    ///
+    /// The queues are used as they are, including their batch sizes and
+    /// [`with_spins_before_yield`](CohortFifo::with_spins_before_yield) and
+    /// [`with_strict_ordering`](CohortFifo::with_strict_ordering) settings;
+    /// the receiver's batch size is the output batch size. Like
+    /// [`try_register`](Self::try_register) this fails if the kernel rejects
+    /// the cohort.
+    ///
+    /// # Safety
+    ///
+    /// The cohort id must not currently be in use. Both queues must be
+    /// empty, and their buffers must not overlap each other and must stay
+    /// valid for as long as the cohort exists; buffers a fifo allocated
+    /// itself always do, mapped ones are the caller's responsibility. The
+    /// fifo constructors already guarantee the 128-byte alignment the
+    /// accelerator needs.
+    pub unsafe fn from_fifos(
+        id: u8,
+        sender: CohortFifo<S>,
+        receiver: CohortFifo<R>,
+    ) -> Result<Pin<Box<Self>>> {
+        let config = CohortBuilder::new().id(id);
+        let cohort = Self::assemble(&config, sender, receiver);
+        cohort.cohort_mn_register()?;
+        Ok(cohort)
+    }
+
+    /// Registers a cohort for an accelerator that only consumes data: only
+    /// the sending queue is allocated, halving the memory of
+    /// [`try_register`](Self::try_register).
+    ///
+    /// The kernel is handed a one-slot placeholder for the receiving queue
+    /// that the accelerator never sees data on. Pops on the cohort fail with
+    /// [`Error::DirectionDisabled`], or panic for the blocking ones. Like
+    /// [`try_register`](Self::try_register) this fails if the kernel
+    /// rejects the cohort.
+    ///
+    /// # Safety
+    ///
+    /// The cohort id must not currently be in use.
+    pub unsafe fn register_sink(
+        id: u8,
+        capacity: usize,
+        batch_size: usize,
+    ) -> Result<Pin<Box<Self>>> {
+        let config = CohortBuilder::new()
+            .id(id)
+            .capacity(capacity)
+            .batch_size(batch_size);
+        let sender = CohortFifo::new(capacity, batch_size)?;
+        let cohort = Self::assemble(&config, config.tune(sender), CohortFifo::placeholder()?);
+        cohort.cohort_mn_register()?;
+        Ok(cohort)
+    }
+
+    /// Registers a cohort for an accelerator that only produces data: only
+    /// the receiving queue is allocated.
+    ///
+    /// The inverse of [`register_sink`](Self::register_sink): pushes on the
+    /// cohort fail with [`Error::DirectionDisabled`], or panic for the
+    /// blocking ones.
+    ///
+    /// # Safety
+    ///
+    /// The cohort id must not currently be in use.
+    pub unsafe fn register_source(
+        id: u8,
+        capacity: usize,
+        batch_size: usize,
+    ) -> Result<Pin<Box<Self>>> {
+        let config = CohortBuilder::new()
+            .id(id)
+            .capacity(capacity)
+            .batch_size(batch_size);
+        let receiver = CohortFifo::new(capacity, batch_size)?;
+        let cohort = Self::assemble(&config, CohortFifo::placeholder()?, config.tune(receiver));
+        cohort.cohort_mn_register()?;
+        Ok(cohort)
+    }
 
-        cohort
+    fn assemble(
+        config: &CohortBuilder,
+        sender: CohortFifo<S>,
+        receiver: CohortFifo<R>,
+    ) -> Pin<Box<Self>> {
+        // In verify mode the word holds the accelerator's count, which
+        // starts from nothing answered.
+        let custom_data = if config.verify { 0 } else { config.custom_data };
+        Box::pin(Cohort {
+            _id: config.id,
+            sender,
+            receiver,
+            custom_data: Aligned(AtomicU64::new(custom_data)),
+            backoff: config.backoff,
+            registered: AtomicBool::new(false),
+            on_unregister_error: config.on_unregister_error,
+            verify: config.verify,
+            pushed_elems: AtomicU64::new(0),
+            popped_elems: AtomicU64::new(0),
+            stats: stats::Counters::new(),
+            producer_taken: AtomicBool::new(false),
+            consumer_taken: AtomicBool::new(false),
+            #[cfg(target_os = "linux")]
+            readiness: std::sync::OnceLock::new(),
+            _pin: PhantomPinned,
+        })
     }
 
     /// Sends an element to the accelerator.
     ///
     /// May block if the sending end is full.
-    pub fn push(&self, elem1: &T, elem2: &T) {
-        self.sender.push(elem1, elem2);
+    ///
+    /// # Panics
+    ///
+    /// Panics on a cohort made with [`register_source`](Self::register_source).
+    ///
+    /// # Safety
+    ///
+    /// No other push onto the cohort may run at the same time, on any
+    /// thread. Pushing only through the cohort's [`Producer`] rules this out.
+    pub unsafe fn push(&self, elem1: &S, elem2: &S) {
+        self.blocking_push(2, || self.sender.try_push(elem1, elem2));
     }
 
     /// Receives an element from the accelerator.
     ///
     /// May block if the receiving end is full.
-    pub fn pop(&self, elem1: &mut T, elem2: &mut T) {
-        self.receiver.pop(elem1, elem2)
+    ///
+    /// # Panics
+    ///
+    /// In verify mode, panics on a sequence mismatch. Use
+    /// [`try_pop`](Self::try_pop) to get it as an error instead. Also panics
+    /// on a cohort made with [`register_sink`](Self::register_sink).
+    ///
+    /// # Safety
+    ///
+    /// No other pop from the cohort may run at the same time, on any thread.
+    /// Popping only through the cohort's [`Consumer`] rules this out.
+    pub unsafe fn pop(&self, elem1: &mut R, elem2: &mut R) {
+        self.blocking_pop(2, || self.receiver.try_pop(elem1, elem2));
     }
 
-    /// Sends an element to the accelerator.
+    /// Sends a pair given as one array, like [`push`](Self::push).
     ///
-    /// Will fail if the sending end is full.
-    pub fn try_push(&self, elem1: &T, elem2: &T) -> Result<(), ()> {
-        self.sender.try_push(elem1, elem2)
+    /// May block if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn push_pair(&self, pair: &[S; 2]) {
+        unsafe { self.push(&pair[0], &pair[1]) };
+    }
+
+    /// Receives a pair into one array, like [`pop`](Self::pop).
+    ///
+    /// May block if the receiving end is empty.
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn pop_pair(&self, out: &mut [R; 2]) {
+        let [elem1, elem2] = out;
+        unsafe { self.pop(elem1, elem2) };
     }
 
+    /// Sends an element to the accelerator.
+    ///
+    /// Will fail if the sending end is full, or with
+    /// [`Error::DirectionDisabled`] on a cohort made with
+    /// [`register_source`](Self::register_source).
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn try_push(&self, elem1: &S, elem2: &S) -> Result<()> {
+        self.counted_push(2, || self.sender.try_push(elem1, elem2))
+    }
 
     /// Receives an element from the accelerator.
     ///
-    /// Will fail if receiving end is full.
-    pub fn try_pop(&self, elem1: &mut T, elem2: &mut T) -> Result<(), ()> {
-        self.receiver.try_pop(elem1, elem2)
+    /// Will fail with [`Error::Empty`] if the receiving end is empty, with
+    /// [`Error::PartialElement`] if only the first element of the next pair
+    /// has arrived, or in verify mode with [`Error::SequenceMismatch`] if
+    /// pairs were dropped or duplicated. On a cohort made with
+    /// [`register_sink`](Self::register_sink) it always fails with
+    /// [`Error::DirectionDisabled`].
+    ///
+    /// A pair is only taken once both of its elements are published, so if
+    /// the accelerator ends a stream on an odd number of elements the last
+    /// one is never returned here; take it with
+    /// [`try_pop_one`](Self::try_pop_one).
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn try_pop(&self, elem1: &mut R, elem2: &mut R) -> Result<()> {
+        self.counted_pop(2, || self.receiver.try_pop(elem1, elem2))
     }
 
-    pub fn print_receiver(&self){
-        self.receiver.print_queue();
+    /// Panics if the queue of the given capacity is the placeholder of a
+    /// sink or source cohort, rather than block on it forever.
+    fn assert_enabled(&self, capacity: usize) {
+        if capacity == 0 {
+            panic!("cohort {}: {}", self._id, Error::DirectionDisabled);
+        }
     }
 
-    pub fn print_sender(&self){
-        self.sender.print_queue();
+    /// Receives a pair if one is available, returning true, or otherwise
+    /// fills both outputs with `R::default()` and returns false.
+    ///
+    /// For consumers that would rather carry on with a placeholder than
+    /// wait. A half-arrived pair counts as unavailable.
+    ///
+    /// # Panics
+    ///
+    /// Panics wherever [`pop`](Self::pop) would.
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn pop_or_default(&self, elem1: &mut R, elem2: &mut R) -> bool
+    where
+        R: Default,
+    {
+        match unsafe { self.try_pop(elem1, elem2) } {
+            Ok(()) => true,
+            Err(Error::Empty | Error::PartialElement) => {
+                *elem1 = R::default();
+                *elem2 = R::default();
+                false
+            }
+            Err(err) => panic!("cohort {}: {}", self._id, err),
+        }
     }
 
-}
+    /// In verify mode, counts `count` elements about to be pushed.
+    ///
+    /// This runs before the push publishes them, so the count already
+    /// covers anything the accelerator can have answered.
+    fn reserve_pushed(&self, count: usize) {
+        if self.verify {
+            self.pushed_elems.fetch_add(count as u64, Ordering::Relaxed);
+        }
+    }
 
-impl<T: Copy + std::fmt::Debug> Drop for Cohort<T> {
-    fn drop(&mut self) {
-        unsafe {
-            //TODO: check status from syscall
+    /// Takes back the part of a [`reserve_pushed`](Self::reserve_pushed)
+    /// that was not pushed after all.
+    fn refund_pushed(&self, count: usize) {
+        if self.verify && count > 0 {
+            self.pushed_elems.fetch_sub(count as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Runs a push of `count` elements that either sends all of them or
+    /// fails having sent none, counting it in the stats and in verify mode.
+    fn counted_push<T>(&self, count: usize, push: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.reserve_pushed(count);
+        match push().map_err(|err| disabled_or(&self.sender, err)) {
+            Ok(val) => {
+                self.stats.pushed(count);
+                Ok(val)
+            }
+            Err(err) => {
+                self.refund_pushed(count);
+                if matches!(err, Error::Full) {
+                    self.stats.rejected_full();
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Retries a push of `count` elements until it succeeds, like
+    /// [`counted_push`](Self::counted_push) but counting the spins instead.
+    fn blocking_push(&self, count: usize, mut push: impl FnMut() -> Result<()>) {
+        self.assert_enabled(self.sender.capacity());
+        self.reserve_pushed(count);
+        let mut backoff = self.sender.backoff();
+        while push().is_err() {
+            self.stats.push_spun();
+            backoff.snooze();
+        }
+        self.stats.pushed(count);
+    }
+
+    /// Runs a pop of `count` elements that either receives all of them or
+    /// fails having received none, counting it in the stats and checking it
+    /// in verify mode.
+    fn counted_pop<T>(&self, count: usize, pop: impl FnOnce() -> Result<T>) -> Result<T> {
+        let val = pop()
+            .map_err(|err| disabled_or(&self.receiver, err))
+            .inspect_err(|err| {
+                if matches!(err, Error::Empty | Error::PartialElement) {
+                    self.stats.rejected_empty();
+                }
+            })?;
+        self.check_popped(count)?;
+        Ok(val)
+    }
+
+    /// Retries a pop of `count` elements until it succeeds, like
+    /// [`counted_pop`](Self::counted_pop) but counting the spins instead and
+    /// panicking on a sequence mismatch.
+    fn blocking_pop<T>(&self, count: usize, mut pop: impl FnMut() -> Result<T>) -> T {
+        self.assert_enabled(self.receiver.capacity());
+        let mut backoff = self.receiver.backoff();
+        let val = loop {
+            if let Ok(val) = pop() {
+                break val;
+            }
+            self.stats.pop_spun();
+            backoff.snooze();
+        };
+        self.assert_popped(count);
+        val
+    }
+
+    /// Counts `count` popped elements in the stats and, in verify mode,
+    /// checks them against the elements pushed and the number of elements
+    /// the accelerator reports having answered in the custom data word.
+    ///
+    /// No more elements may be popped than were pushed, and every element
+    /// answered must have been popped or be waiting in the receiver.
+    fn check_popped(&self, count: usize) -> Result<()> {
+        self.stats.popped(count);
+        if !self.verify || count == 0 {
+            return Ok(());
+        }
+        let popped = self.popped_elems.fetch_add(count as u64, Ordering::Relaxed) + count as u64;
+        let pushed = self.pushed_elems.load(Ordering::Relaxed);
+        if popped > pushed {
+            return Err(Error::SequenceMismatch {
+                expected: pushed,
+                got: popped,
+            });
+        }
+        // The accelerator publishes its output before raising the count, so
+        // with the count read first every element it covers is visible below.
+        let answered = self.get_custom_data();
+        let seen = popped + self.receiver.available() as u64;
+        if seen < answered {
+            return Err(Error::SequenceMismatch {
+                expected: answered,
+                got: seen,
+            });
+        }
+        Ok(())
+    }
+
+    /// Like [`check_popped`](Self::check_popped), for the pops that panic on
+    /// a sequence mismatch.
+    fn assert_popped(&self, count: usize) {
+        if let Err(err) = self.check_popped(count) {
+            panic!("cohort {}: {}", self._id, err);
+        }
+    }
+
+    /// Copies out the next pair of elements from the accelerator without
+    /// removing them.
+    ///
+    /// Will fail if the receiving end holds fewer than two elements, with
+    /// [`Error::Empty`] or [`Error::PartialElement`] like
+    /// [`try_pop`](Self::try_pop), or with [`Error::DirectionDisabled`] on
+    /// a cohort made with [`register_sink`](Self::register_sink).
+    pub fn peek(&self, elem1: &mut R, elem2: &mut R) -> Result<()> {
+        self.receiver
+            .try_peek(elem1, elem2)
+            .map_err(|err| disabled_or(&self.receiver, err))
+    }
+
+    /// Makes every element pushed so far visible to the accelerator.
+    ///
+    /// Pushes only reach the accelerator once a full batch has accumulated,
+    /// so call this after the last push of a request to have it processed
+    /// without padding.
+    pub fn flush(&self) {
+        self.sender.flush();
+    }
+
+    /// Receives an element from the accelerator, giving up after `timeout`.
+    ///
+    /// Returns [`Error::Empty`] if nothing arrived in time.
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn pop_timeout(
+        &self,
+        elem1: &mut R,
+        elem2: &mut R,
+        timeout: Duration,
+    ) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // Only read the clock every so often to keep the spin tight.
+            for _ in 0..TIMEOUT_CHECK_INTERVAL {
+                match unsafe { self.try_pop(elem1, elem2) } {
+                    Err(Error::Empty | Error::PartialElement) => std::hint::spin_loop(),
+                    result => return result,
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::Empty);
+            }
+        }
+    }
+
+    /// Receives pairs into `out` until it is full or `window` has elapsed,
+    /// whichever comes first, and returns how many were received.
+    ///
+    /// Unlike [`pop_slice`](Self::pop_slice) this keeps waiting for pairs the
+    /// accelerator publishes after the call starts, re-reading the hw_tail
+    /// between drains, so output arriving in bursts is collected in one call.
+    ///
+    /// # Panics
+    ///
+    /// In verify mode, panics on a sequence mismatch, like [`pop`](Self::pop).
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn pop_window(&self, out: &mut [(R, R)], window: Duration) -> usize {
+        let deadline = Instant::now() + window;
+        let mut count = 0;
+        while let Some((elem1, elem2)) = out.get_mut(count) {
+            match unsafe { self.try_pop(elem1, elem2) } {
+                Ok(()) => {
+                    count += 1;
+                    continue;
+                }
+                Err(Error::Empty | Error::PartialElement) => {}
+                Err(err) => panic!("cohort {}: {}", self._id, err),
+            }
+            // Only read the clock every so often to keep the spin tight.
+            for _ in 0..TIMEOUT_CHECK_INTERVAL {
+                if self.receiver.available() >= 2 {
+                    break;
+                }
+                std::hint::spin_loop();
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+        count
+    }
+
+    /// Sends one request pair, flushes it so the accelerator sees it at
+    /// once, then waits up to `timeout` for the response pair.
+    ///
+    /// Returns [`Error::Empty`] if no response arrived in time, like
+    /// [`pop_timeout`](Self::pop_timeout). The timeout only covers the
+    /// wait for the response: the push blocks like [`push`](Self::push) if
+    /// the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for both [`push`](Self::push) and [`pop`](Self::pop).
+    pub unsafe fn request_response(
+        &self,
+        req: (&S, &S),
+        resp: (&mut R, &mut R),
+        timeout: Duration,
+    ) -> Result<()> {
+        unsafe { self.push(req.0, req.1) };
+        self.flush();
+        unsafe { self.pop_timeout(resp.0, resp.1, timeout) }
+    }
+
+    /// Flushes, then waits until the accelerator has consumed everything
+    /// pushed so far.
+    ///
+    /// Progress is read from the head the accelerator advances, so this
+    /// returns as soon as the last element has been taken, whether or not
+    /// its answer has arrived yet.
+    pub fn wait_sender_drained(&self) {
+        self.sender.flush();
+        self.wait_until(None, || self.sender.is_empty());
+    }
+
+    /// Like [`wait_sender_drained`](Self::wait_sender_drained), giving up
+    /// after `timeout`.
+    ///
+    /// Returns false if the accelerator had not consumed everything in time.
+    pub fn wait_sender_drained_timeout(&self, timeout: Duration) -> bool {
+        self.sender.flush();
+        self.wait_until(Some(timeout), || self.sender.is_empty())
+    }
+
+    /// Waits until everything the accelerator has made available has been
+    /// popped, e.g. by another thread holding the [`CohortReceiver`].
+    pub fn wait_receiver_empty(&self) {
+        self.wait_until(None, || self.receiver_is_empty());
+    }
+
+    /// Like [`wait_receiver_empty`](Self::wait_receiver_empty), giving up
+    /// after `timeout`.
+    ///
+    /// Returns false if the receiving end still held elements in time.
+    pub fn wait_receiver_empty_timeout(&self, timeout: Duration) -> bool {
+        self.wait_until(Some(timeout), || self.receiver_is_empty())
+    }
+
+    /// Waits until the receiving end holds at least `n` elements, so they can
+    /// all be taken with one [`pop_slice`](Self::pop_slice).
+    ///
+    /// Counts elements like [`receiver_len`](Self::receiver_len), so `n`
+    /// responses of one pair each are `2 * n` elements. Returns
+    /// [`Error::Empty`] if fewer had arrived after `timeout`, which is
+    /// always the case if `n` exceeds the capacity.
+    pub fn wait_for_response_count(&self, n: usize, timeout: Duration) -> Result<()> {
+        if self.wait_until(Some(timeout), || self.receiver_len() >= n) {
+            Ok(())
+        } else {
+            Err(Error::Empty)
+        }
+    }
 
-            //TODO: This drop function doesn't seem to work
-            // and we are forced to re-boot the system 
-            // everytime we want to connect to cohort again
+    /// Spins until `done` returns true or `timeout` runs out, returning
+    /// whether `done` did.
+    fn wait_until(&self, timeout: Option<Duration>, done: impl Fn() -> bool) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut backoff = self.sender.backoff();
+        let mut spins = 0;
+        while !done() {
+            spins += 1;
+            // Only read the clock every so often to keep the spin tight.
+            if spins % TIMEOUT_CHECK_INTERVAL == 0
+                && deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return false;
+            }
+            backoff.snooze();
+        }
+        true
+    }
 
-            // we need to figure out how to make it analagous to the code here:
-            // https://github.com/pengwing-project/cohort-private/blob/cohort/piton/verif/diag/c/riscv/ariane/cohort_linux/cohort_aes_base.c
+    /// Sends an element to the accelerator, attempting
+    /// [`try_push`](Self::try_push) at most `max_spins` times while the
+    /// sending end is full.
+    ///
+    /// Unlike [`push`](Self::push) the wait is bounded by a number of
+    /// attempts rather than time, so a scheduler gets
+    /// [`Error::Full`] back deterministically. With `max_spins` of 0 nothing
+    /// is attempted and this fails with [`Error::Full`] at once.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn push_bounded(&self, elem1: &S, elem2: &S, max_spins: u32) -> Result<()> {
+        let mut result = Err(Error::Full);
+        for spin in 0..max_spins {
+            if spin > 0 {
+                std::hint::spin_loop();
+            }
+            result = unsafe { self.try_push(elem1, elem2) };
+            if !matches!(result, Err(Error::Full)) {
+                break;
+            }
+        }
+        result
+    }
 
-            // Maybe it's just an issue with how it's used in Demikernel?
-            // Need to test this
-            libc::syscall(257);
+    /// Receives an element from the accelerator, attempting
+    /// [`try_pop`](Self::try_pop) at most `max_spins` times while the
+    /// receiving end is empty.
+    ///
+    /// Returns [`Error::Empty`] if nothing arrived within the attempts. With
+    /// `max_spins` of 0 nothing is attempted and this fails with
+    /// [`Error::Empty`] at once.
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn pop_bounded(&self, elem1: &mut R, elem2: &mut R, max_spins: u32) -> Result<()> {
+        let mut result = Err(Error::Empty);
+        for spin in 0..max_spins {
+            if spin > 0 {
+                std::hint::spin_loop();
+            }
+            result = unsafe { self.try_pop(elem1, elem2) };
+            if !matches!(result, Err(Error::Empty | Error::PartialElement)) {
+                break;
+            }
         }
+        result
+    }
+
+    /// Sends a pair of elements built in place by `f`.
+    ///
+    /// `f` writes directly into the sending end, which avoids copying large
+    /// elements. Will fail without calling `f` if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn try_push_with(&self, f: impl FnOnce(&mut S, &mut S)) -> Result<()> {
+        self.counted_push(2, || self.sender.try_push_with(f))
+    }
+
+    /// Sends exactly one batch of pairs to the accelerator and flushes it,
+    /// for one hardware batch per call.
+    ///
+    /// `batch` must hold half as many pairs as the
+    /// [`batch_size`](Self::batch_size), or this fails with [`Error::BatchLengthMismatch`]. Will fail with
+    /// [`Error::Full`] without sending anything if the sending end has no
+    /// room for the whole batch.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn batch_push(&self, batch: &[(S, S)]) -> Result<()> {
+        self.counted_push(2 * batch.len(), || self.sender.try_push_batch(batch))
+    }
+
+    /// Sends a single element to the accelerator.
+    ///
+    /// May block if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn push_one(&self, elem: &S) {
+        self.blocking_push(1, || self.sender.try_push_one(elem));
+    }
+
+    /// Receives a single element from the accelerator.
+    ///
+    /// May block if the receiving end is empty.
+    ///
+    /// # Panics
+    ///
+    /// In verify mode, panics on a sequence mismatch, like [`pop`](Self::pop).
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn pop_one(&self, elem: &mut R) {
+        self.blocking_pop(1, || self.receiver.try_pop_one(elem));
+    }
+
+    /// Sends a single element to the accelerator.
+    ///
+    /// Will fail if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn try_push_one(&self, elem: &S) -> Result<()> {
+        self.counted_push(1, || self.sender.try_push_one(elem))
+    }
+
+    /// Receives a single element from the accelerator.
+    ///
+    /// Will fail if the receiving end is empty.
+    ///
+    /// This also recovers the element left over when a stream ends on an
+    /// odd count. Mixing it with the pair pops is only safe at such an end,
+    /// once the accelerator is known to be done: before then a lone element
+    /// may be the first half of a pair still being written, and taking it
+    /// would misalign every pair after it.
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn try_pop_one(&self, elem: &mut R) -> Result<()> {
+        self.counted_pop(1, || self.receiver.try_pop_one(elem))
+    }
+
+    /// Sends `N` elements to the accelerator as a single transaction, for
+    /// accelerators that do not work in pairs.
+    ///
+    /// May block if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn push_n<const N: usize>(&self, elems: &[S; N]) {
+        self.blocking_push(N, || self.sender.try_push_n(elems));
+    }
+
+    /// Receives a transaction of `N` elements from the accelerator.
+    ///
+    /// May block until all `N` elements have arrived.
+    ///
+    /// # Panics
+    ///
+    /// In verify mode, panics on a sequence mismatch, like [`pop`](Self::pop).
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn pop_n<const N: usize>(&self) -> [R; N] {
+        self.blocking_pop(N, || self.receiver.try_pop_n())
+    }
+
+    /// Sends `N` elements to the accelerator as a single transaction.
+    ///
+    /// Will fail without sending any of them if the sending end has room
+    /// for fewer than `N` elements.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn try_push_n<const N: usize>(&self, elems: &[S; N]) -> Result<()> {
+        self.counted_push(N, || self.sender.try_push_n(elems))
+    }
+
+    /// Receives a transaction of `N` elements from the accelerator.
+    ///
+    /// Will fail without receiving any of them if fewer than `N` elements
+    /// are available, or in verify mode with [`Error::SequenceMismatch`]
+    /// after receiving them.
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn try_pop_n<const N: usize>(&self) -> Result<[R; N]> {
+        self.counted_pop(N, || self.receiver.try_pop_n())
+    }
+
+    /// Sends every element of `data` to the accelerator.
+    ///
+    /// May block if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn push_slice(&self, data: &[S]) {
+        self.assert_enabled(self.sender.capacity());
+        self.reserve_pushed(data.len());
+        let mut pushed = 0;
+        let mut backoff = self.sender.backoff();
+        while pushed < data.len() {
+            match self.sender.try_push_slice(&data[pushed..]) {
+                0 => {
+                    self.stats.push_spun();
+                    backoff.snooze();
+                }
+                count => pushed += count,
+            }
+        }
+        self.stats.pushed(data.len());
+    }
+
+    /// Sends every pair in `items` to the accelerator, then flushes.
+    ///
+    /// May block if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn extend_from_slice(&self, items: &[(S, S)]) {
+        unsafe { self.extend(items.iter().copied()) };
+    }
+
+    /// Sends every pair yielded by `items` to the accelerator, then flushes.
+    ///
+    /// Flushing once at the end makes the last partial batch visible without
+    /// a flush per pair. May block if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn extend<I: IntoIterator<Item = (S, S)>>(&self, items: I) {
+        unsafe { self.push_from_iter(items.into_iter()) };
+    }
+
+    /// Sends the pair `f` packs `item` into.
+    ///
+    /// Lets callers keep their own input type and leave packing it into
+    /// queue elements to `f`. May block if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn push_mapped<U>(&self, item: U, f: impl FnOnce(U) -> (S, S)) {
+        let (elem1, elem2) = f(item);
+        unsafe { self.push(&elem1, &elem2) };
+    }
+
+    /// Sends pairs pulled one at a time from `iter` until it is exhausted,
+    /// then flushes, and returns the number of pairs sent.
+    ///
+    /// Each pair is only generated once there is room for the previous one,
+    /// so a lazy producer never runs ahead of the accelerator by more than
+    /// the capacity of the sending end. May block while the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn push_from_iter<I: Iterator<Item = (S, S)>>(&self, iter: I) -> usize {
+        let mut pushed = 0;
+        for (elem1, elem2) in iter {
+            unsafe { self.push(&elem1, &elem2) };
+            pushed += 1;
+        }
+        self.sender.flush();
+        pushed
+    }
+
+    /// Sends as many pairs of `items` as currently fit, then flushes.
+    ///
+    /// Never blocks. Returns the pairs that did not fit, so a producer can
+    /// retry them later without sending any twice.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn try_push_all<'a>(&self, items: &'a [(S, S)]) -> &'a [(S, S)] {
+        let mut pushed = 0;
+        for (elem1, elem2) in items {
+            if unsafe { self.try_push(elem1, elem2) }.is_err() {
+                break;
+            }
+            pushed += 1;
+        }
+        self.sender.flush();
+        &items[pushed..]
+    }
+
+    /// Moves a bit of data each way without blocking, for event loops that
+    /// drive the cohort once per tick.
+    ///
+    /// Sends up to `max_in` pairs from the front of `input`, stopping early
+    /// if the sending end fills up, and flushes them. Then receives up to
+    /// `max_out` pairs into the front of `output`, stopping early once the
+    /// receiving end is empty. Returns the number of pairs sent and received.
+    ///
+    /// In verify mode a sequence mismatch also stops receiving, after the
+    /// mismatched pair; it is counted but not reported, so check with
+    /// [`try_pop`](Self::try_pop) when that matters.
+    ///
+    /// # Safety
+    ///
+    /// As for both [`push`](Self::push) and [`pop`](Self::pop).
+    pub unsafe fn pump(
+        &self,
+        max_in: usize,
+        input: &[(S, S)],
+        max_out: usize,
+        output: &mut [(R, R)],
+    ) -> (usize, usize) {
+        let input = &input[..max_in.min(input.len())];
+        let pushed = input.len() - unsafe { self.try_push_all(input) }.len();
+
+        let max_out = max_out.min(output.len());
+        let mut popped = 0;
+        for (elem1, elem2) in &mut output[..max_out] {
+            match unsafe { self.try_pop(elem1, elem2) } {
+                Ok(()) => popped += 1,
+                Err(Error::SequenceMismatch { .. }) => {
+                    popped += 1;
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+        (pushed, popped)
+    }
+
+    /// Sends every pair of `input` to the accelerator and receives
+    /// `output.len()` pairs back.
+    ///
+    /// Pushing and popping are interleaved, so `input` may be longer than
+    /// the queues can hold without the accelerator stalling on a full
+    /// receiver. Blocks until all of `input` has been sent and `output` has
+    /// been filled. The accelerator is expected to answer every pair of
+    /// `input` with one pair. If `output` is shorter than `input` the
+    /// answers that do not fit are received and dropped, so they never fill
+    /// up the receiving end and stall the accelerator; if it is longer the
+    /// accelerator must answer with that many pairs for this to return.
+    ///
+    /// In verify mode, fails with [`Error::SequenceMismatch`] like
+    /// [`try_pop`](Self::try_pop).
+    ///
+    /// # Safety
+    ///
+    /// As for both [`push`](Self::push) and [`pop`](Self::pop).
+    pub unsafe fn roundtrip(&self, input: &[(S, S)], output: &mut [(R, R)]) -> Result<()> {
+        let answers = input.len().max(output.len());
+        let mut pending = input;
+        let mut popped = 0;
+        // Like the queue buffers, this relies on all-zero bytes being a
+        // valid element.
+        let mut surplus: (R, R) = unsafe { mem::zeroed() };
+        let mut backoff = self.receiver.backoff();
+        while !pending.is_empty() || popped < answers {
+            let before = (pending.len(), popped);
+            if !pending.is_empty() {
+                pending = unsafe { self.try_push_all(pending) };
+            }
+            while popped < answers {
+                let (elem1, elem2) = output.get_mut(popped).unwrap_or(&mut surplus);
+                match unsafe { self.try_pop(elem1, elem2) } {
+                    Ok(()) => popped += 1,
+                    Err(Error::Empty | Error::PartialElement) => break,
+                    Err(err) => return Err(err),
+                }
+            }
+            if (pending.len(), popped) == before {
+                backoff.snooze();
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends as many elements of `data` to the accelerator as currently fit.
+    ///
+    /// Returns the number of elements sent.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Self::push).
+    pub unsafe fn try_push_slice(&self, data: &[S]) -> usize {
+        self.reserve_pushed(data.len());
+        let pushed = self.sender.try_push_slice(data);
+        self.refund_pushed(data.len() - pushed);
+        self.stats.pushed(pushed);
+        if pushed == 0 && !data.is_empty() && self.sender.capacity() > 0 {
+            self.stats.rejected_full();
+        }
+        pushed
+    }
+
+    /// Receives as many elements from the accelerator as are available into `out`.
+    ///
+    /// Returns the number of elements received.
+    ///
+    /// # Panics
+    ///
+    /// In verify mode, panics on a sequence mismatch, like [`pop`](Self::pop).
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn pop_slice(&self, out: &mut [R]) -> usize {
+        let count = self.receiver.pop_slice(out);
+        self.assert_popped(count);
+        count
+    }
+
+    /// Receives whole output batches from the accelerator into `out`.
+    ///
+    /// Waits until at least one batch of [`out_batch_size`](Self::out_batch_size)
+    /// elements is available, then receives as many whole batches as are
+    /// available and fit in `out`. Returns the number of elements received.
+    ///
+    /// This matches accelerators that only publish their results in chunks of
+    /// a fixed size: set the output batch size to that chunk size with
+    /// [`CohortBuilder::out_batch_size`] so each batch received is one
+    /// complete chunk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is shorter than one output batch, or in verify mode
+    /// on a sequence mismatch, like [`pop`](Self::pop).
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn pop_batch(&self, out: &mut [R]) -> usize {
+        let batch_size = self.receiver.batch_size();
+        let mut backoff = self.receiver.backoff();
+        // A short `out` goes straight on to the panic in pop_batch.
+        while out.len() >= batch_size && self.receiver.available() < batch_size {
+            self.stats.pop_spun();
+            backoff.snooze();
+        }
+        let count = self.receiver.pop_batch(out);
+        self.assert_popped(count);
+        count
+    }
+
+    /// Receives exactly `out.len()` elements from the accelerator.
+    ///
+    /// Will fail without receiving anything if fewer elements are available,
+    /// so a fixed-size frame is never delivered in part. In verify mode,
+    /// fails with [`Error::SequenceMismatch`] after receiving them.
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn try_pop_exact(&self, out: &mut [R]) -> Result<()> {
+        self.counted_pop(out.len(), || self.receiver.try_pop_exact(out))
+    }
+
+    /// Returns a descriptor that becomes readable when the accelerator has
+    /// made output available, for waiting with `poll` or `epoll` instead of
+    /// spinning.
+    ///
+    /// The descriptor is an eventfd owned by the cohort and created on the
+    /// first call; later calls return the same one. It is level-triggered:
+    /// it stays readable from the first time output is published until
+    /// [`clear_readiness`](Self::clear_readiness) is called, even once the
+    /// receiver has been drained. To not miss output, clear it before
+    /// draining the receiver rather than after.
+    ///
+    /// Only the `LoopbackAccelerator` of the `mock` feature
+    /// signals the descriptor for now; the Cohort kernel interface has no
+    /// way to. Fails with [`Error::ReadinessFailed`] if the eventfd cannot
+    /// be created.
+    #[cfg(target_os = "linux")]
+    pub fn readiness_fd(&self) -> Result<RawFd> {
+        if let Some(fd) = self.readiness.get() {
+            return Ok(fd.as_raw_fd());
+        }
+        let fd = sys::eventfd().map_err(Error::ReadinessFailed)?;
+        // If another thread won the race, its descriptor is kept and ours closed.
+        let _ = self.readiness.set(fd);
+        Ok(self.readiness.get().unwrap().as_raw_fd())
+    }
+
+    /// Makes the [`readiness_fd`](Self::readiness_fd) unreadable until the
+    /// accelerator publishes more output.
+    #[cfg(target_os = "linux")]
+    pub fn clear_readiness(&self) {
+        if let Some(fd) = self.readiness.get() {
+            sys::eventfd_clear(fd.as_fd());
+        }
+    }
+
+    /// Signals the readiness descriptor, if one has been handed out.
+    #[cfg(all(target_os = "linux", feature = "mock"))]
+    pub(crate) fn signal_ready(&self) {
+        if let Some(fd) = self.readiness.get() {
+            sys::eventfd_signal(fd.as_fd());
+        }
+    }
+
+    /// Splits the cohort into a sending and a receiving half.
+    ///
+    /// The halves share ownership of the cohort, so one thread can push while
+    /// another pops. The cohort is unregistered once both halves are dropped.
+    pub fn split(self: Pin<Box<Self>>) -> (CohortSender<S, R>, CohortReceiver<S, R>) {
+        split::split(self)
+    }
+
+    /// Stores a new value in the custom data word.
+    ///
+    /// The custom data word is handed to the kernel alongside the queues at
+    /// registration, and its meaning is defined by each accelerator (for
+    /// example a configuration word or a status flag). The store uses
+    /// release ordering so writes made before it are visible to whoever
+    /// observes the new value.
+    pub fn set_custom_data(&self, val: u64) {
+        self.custom_data.0.store(val, Ordering::Release);
+    }
+
+    /// Loads the current value of the custom data word.
+    ///
+    /// See [`set_custom_data`](Self::set_custom_data). The load uses acquire
+    /// ordering, pairing with the release store.
+    pub fn get_custom_data(&self) -> u64 {
+        self.custom_data.0.load(Ordering::Acquire)
+    }
+
+    /// Returns an iterator over the elements the accelerator has made available.
+    ///
+    /// The iterator stops as soon as the receiving end is empty rather than
+    /// waiting for more data.
+    ///
+    /// ```no_run
+    /// # use cohort::Cohort;
+    /// # let cohort = unsafe { Cohort::<u64>::register(0, 32, 8) }.unwrap();
+    /// // SAFETY: Nothing else pops from the cohort.
+    /// for word in unsafe { cohort.drain() } {
+    ///     println!("{word:x}");
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop), for as long as the iterator lives: nothing
+    /// else may pop from the cohort while it is iterated.
+    pub unsafe fn drain(&self) -> Drain<'_, S, R> {
+        Drain::new(self)
+    }
+
+    /// Receives every pair the accelerator has made available into a new `Vec`.
+    ///
+    /// Like [`drain`](Self::drain) this never waits, and stops at the first
+    /// pair that has not been published yet.
+    ///
+    /// # Panics
+    ///
+    /// In verify mode, panics on a sequence mismatch, like [`pop`](Self::pop).
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Self::pop).
+    pub unsafe fn drain_into_vec(&self) -> Vec<(R, R)> {
+        let mut pairs = Vec::with_capacity(self.receiver.available() / 2);
+        // try_pop_n reads the hw_tail, so everything published so far is seen.
+        while let Ok([elem1, elem2]) = self.receiver.try_pop_n() {
+            pairs.push((elem1, elem2));
+        }
+        self.assert_popped(2 * pairs.len());
+        pairs
+    }
+
+    /// Empties both ends so the cohort can be reused without reallocating.
+    ///
+    /// # Safety
+    ///
+    /// The accelerator must not be reading or writing the queue pointers
+    /// while this runs, e.g. between benchmark runs with the engine idle.
+    /// In verify mode the accelerator must also start counting its answers
+    /// from 0 again, as the cohort does.
+    pub unsafe fn reset(&self) {
+        unsafe {
+            self.sender.reset();
+            self.receiver.reset();
+        }
+        if self.verify {
+            self.pushed_elems.store(0, Ordering::Relaxed);
+            self.popped_elems.store(0, Ordering::Relaxed);
+            self.set_custom_data(0);
+        }
+    }
+
+    /// Returns a snapshot of the push and pop counters.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> CohortStats {
+        self.stats.snapshot()
+    }
+
+    /// Zeroes the push and pop counters, returning their final values.
+    ///
+    /// Each counter is swapped with zero on its own, so nothing counted
+    /// concurrently is lost: it shows up either in the returned values or in
+    /// the next snapshot. Lets a profiler sample deltas without stopping the
+    /// workload.
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&self) -> CohortStats {
+        self.stats.reset()
+    }
+
+    /// Returns the sending end, e.g. to log its pointers with its
+    /// [`Display`](core::fmt::Display) implementation.
+    ///
+    /// Pushing onto it directly skips the cohort's stats and sequence checks.
+    pub fn sender(&self) -> &CohortFifo<S> {
+        &self.sender
+    }
+
+    /// Returns the receiving end, e.g. to log its pointers with its
+    /// [`Display`](core::fmt::Display) implementation.
+    ///
+    /// Popping from it directly skips the cohort's stats and sequence checks.
+    pub fn receiver(&self) -> &CohortFifo<R> {
+        &self.receiver
+    }
+
+    /// Reads the pointers of the sending end; see [`CohortFifo::snapshot`].
+    pub fn sender_snapshot(&self) -> FifoSnapshot {
+        self.sender.snapshot()
+    }
+
+    /// Reads the pointers of the receiving end; see [`CohortFifo::snapshot`].
+    pub fn receiver_snapshot(&self) -> FifoSnapshot {
+        self.receiver.snapshot()
+    }
+
+    /// Formats the pointers of the sending end; see [`sender`](Self::sender).
+    pub fn sender_to_string(&self) -> String {
+        self.sender.to_string()
+    }
+
+    /// Formats the pointers of the receiving end; see [`receiver`](Self::receiver).
+    pub fn receiver_to_string(&self) -> String {
+        self.receiver.to_string()
+    }
+
+    /// Returns the number of elements each end can hold.
+    pub fn capacity(&self) -> usize {
+        self.sender.capacity()
+    }
+
+    /// Returns the base addresses of the sending and receiving ring buffers.
+    ///
+    /// Both are 128-byte aligned and stay valid for as long as the cohort
+    /// exists; see [`CohortFifo::buffer_ptr`].
+    pub fn buffer_ptrs(&self) -> (*const S, *const R) {
+        (self.sender.buffer_ptr(), self.receiver.buffer_ptr())
+    }
+
+    /// Returns the number of bytes of memory the cohort pins.
+    ///
+    /// This is both queue buffers, as reported by
+    /// [`CohortFifo::capacity_in_bytes`], plus the cohort itself, which holds
+    /// the queue pointers the accelerator reads. Lets a scheduler decide how
+    /// many cohorts fit in limited DMA-able memory.
+    pub fn memory_footprint(&self) -> usize {
+        self.sender.capacity_in_bytes() + self.receiver.capacity_in_bytes() + mem::size_of::<Self>()
+    }
+
+    /// Returns the capacity, batch size and backoff of this cohort, for
+    /// registering an identical one under another id.
+    ///
+    /// ```no_run
+    /// # use cohort::Cohort;
+    /// # let first = unsafe { Cohort::<u64>::try_register(0, 64, 8) }.unwrap();
+    /// // SAFETY: No other cohorts are associated with id 1.
+    /// let second = unsafe { Cohort::<u64>::register_with_config(1, &first.config()) }.unwrap();
+    /// ```
+    pub fn config(&self) -> CohortConfig {
+        // A sink or source only has one real queue.
+        let (capacity, batch_size) = if self.sender.capacity() == 0 {
+            (self.receiver.capacity(), self.receiver.batch_size())
+        } else {
+            (self.sender.capacity(), self.sender.batch_size())
+        };
+        CohortConfig {
+            capacity,
+            batch_size,
+            backoff: self.backoff,
+        }
+    }
+
+    /// Returns a builder set up like this cohort, for registering an
+    /// identical one under another id with every setting of
+    /// [`CohortBuilder`], not just those of [`config`](Self::config).
+    ///
+    /// ```no_run
+    /// # use cohort::Cohort;
+    /// # let first = unsafe { Cohort::<u64>::try_register(0, 64, 8) }.unwrap();
+    /// // SAFETY: No other cohorts are associated with id 1.
+    /// let second = unsafe { first.to_builder().id(1).build::<u64, u64>() }.unwrap();
+    /// ```
+    ///
+    /// Everything but the initial custom data word carries over; a flush
+    /// threshold ratio shows up as the batch size it resolved to.
+    pub fn to_builder(&self) -> CohortBuilder {
+        // A sink or source only tuned the queue it uses.
+        let (capacity, (spins_before_yield, strict_ordering, fast_wrap)) =
+            if self.sender.capacity() == 0 {
+                (self.receiver.capacity(), self.receiver.tuning())
+            } else {
+                (self.sender.capacity(), self.sender.tuning())
+            };
+        CohortBuilder::new()
+            .id(self._id)
+            .capacity(capacity)
+            .batch_size(self.sender.batch_size())
+            .out_batch_size(self.receiver.batch_size())
+            .backoff(self.backoff)
+            .spins_before_yield(spins_before_yield)
+            .strict_ordering(strict_ordering)
+            .fast_wrap(fast_wrap)
+            .verify(self.verify)
+            .on_unregister_error(self.on_unregister_error)
+    }
+
+    /// Returns the backoff counter the cohort is registered with, 240
+    /// unless set with [`CohortBuilder::backoff`].
+    pub fn backoff(&self) -> u64 {
+        self.backoff
+    }
+
+    /// Returns the number of elements pushed before they are handed to the accelerator.
+    pub fn batch_size(&self) -> usize {
+        self.sender.batch_size()
+    }
+
+    /// Returns the number of elements [`pop_batch`](Self::pop_batch) receives at a time.
+    pub fn out_batch_size(&self) -> usize {
+        self.receiver.batch_size()
+    }
+
+    /// Returns the number of elements waiting in the sending end.
+    ///
+    /// This is an instantaneous snapshot: the accelerator may consume
+    /// elements at any time, so the value can shrink as soon as it is returned.
+    pub fn sender_len(&self) -> usize {
+        self.sender.num_elems()
+    }
+
+    /// Returns the number of elements that can still be pushed before the
+    /// sending end is full.
+    ///
+    /// Each pair takes two elements, so a producer can push `free_space / 2`
+    /// pairs without blocking. This is an instantaneous snapshot: the
+    /// accelerator may consume elements at any time, so the value can grow
+    /// as soon as it is returned, but never shrinks unless this side pushes.
+    pub fn sender_free_space(&self) -> usize {
+        self.sender.capacity() - self.sender.num_elems()
+    }
+
+    /// Returns true if every element pushed so far has been consumed by the accelerator.
+    ///
+    /// Like [`sender_len`](Self::sender_len) this is an instantaneous snapshot.
+    pub fn sender_is_empty(&self) -> bool {
+        self.sender.is_empty()
+    }
+
+    /// Returns the number of elements the accelerator has made available in the receiving end.
+    ///
+    /// Each pair is two elements, so `receiver_len() / 2` pairs can be popped
+    /// without blocking. This is an instantaneous snapshot: the accelerator
+    /// may produce elements at any time, so the value can grow as soon as it
+    /// is returned.
+    pub fn receiver_len(&self) -> usize {
+        self.receiver.available()
+    }
+
+    /// Returns true if the accelerator has not made any elements available in the receiving end.
+    ///
+    /// Like [`receiver_len`](Self::receiver_len) this is an instantaneous snapshot.
+    pub fn receiver_is_empty(&self) -> bool {
+        self.receiver.available() == 0
+    }
+
+    /// Hands the queues to the kernel so the accelerator can start using them.
+    pub(crate) fn cohort_mn_register(&self) -> Result<()> {
+        // Claim the registration first, so two racing calls cannot both
+        // issue the syscall.
+        if self.registered.swap(true, Ordering::AcqRel) {
+            return Err(Error::AlreadyRegistered);
+        }
+        unsafe {
+            sys::cohort_mn_register(
+                &self.sender as *const _ as *const _,
+                &self.receiver as *const _ as *const _,
+                &self.custom_data.0 as *const _ as *const _,
+                self.backoff,
+            )
+        }
+        .map_err(|errno| {
+            self.registered.store(false, Ordering::Release);
+            Error::RegistrationFailed(errno)
+        })
+    }
+
+    /// Returns whether the kernel has accepted the cohort and it has not
+    /// been unregistered since.
+    pub fn is_registered(&self) -> bool {
+        self.registered.load(Ordering::Acquire)
+    }
+
+    /// Unregisters the cohort, reporting whether the kernel accepted it.
+    ///
+    /// Dropping a cohort unregisters it too, but can only hand a failure to
+    /// the callback set with
+    /// [`CohortBuilder::on_unregister_error`]. Does nothing if the cohort was
+    /// never successfully registered.
+    pub fn unregister(self: Pin<Box<Self>>) -> Result<()> {
+        if self.registered.swap(false, Ordering::AcqRel) {
+            self.cohort_mn_unregister()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Tells the kernel to tear down the accelerator associated with this cohort's id.
+    fn cohort_mn_unregister(&self) -> Result<()> {
+        unsafe { sys::cohort_mn_unregister(self._id) }.map_err(Error::UnregisterFailed)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: Copy + std::fmt::Debug, R: Copy + std::fmt::Debug> Cohort<S, R> {
+    /// Prints the pointers of the receiver queue.
+    ///
+    /// With the `debug` feature the raw contents of the queue are printed too.
+    pub fn print_receiver(&self){
+        self.receiver.print_queue();
+    }
+
+    /// Prints the pointers of the sender queue.
+    ///
+    /// With the `debug` feature the raw contents of the queue are printed too.
+    pub fn print_sender(&self){
+        self.sender.print_queue();
+    }
+
+}
+
+#[cfg(feature = "std")]
+impl<S: Copy, R: Copy> std::fmt::Debug for Cohort<S, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cohort")
+            .field("id", &self._id)
+            .field("sender", &self.sender)
+            .field("receiver", &self.receiver)
+            .field("custom_data", &self.get_custom_data())
+            .finish()
+    }
+}
+
+/// Accelerator-side operations used to drive a cohort from tests and the
+/// mock accelerator.
+#[cfg(all(any(test, feature = "mock"), feature = "std"))]
+impl<S: Copy, R: Copy> Cohort<S, R> {
+    /// Plays an accelerator that applies `f` to everything published on the
+    /// sender and hands the results to the receiver, as far as the receiver
+    /// has room.
+    ///
+    /// Returns the number of elements moved. In verify mode the custom data
+    /// word is then raised by that many, as the verify protocol asks.
+    pub(crate) fn loopback_with(&self, mut f: impl FnMut(S) -> R) -> usize {
+        let mut moved = 0;
+        while !self.receiver.accel_is_full() {
+            match self.sender.accel_pop() {
+                Some(elem) => self.receiver.accel_push(f(elem)).unwrap(),
+                None => break,
+            }
+            moved += 1;
+        }
+        if self.verify {
+            self.custom_data
+                .0
+                .fetch_add(moved as u64, Ordering::Release);
+        }
+        moved
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+impl<T: Copy> Cohort<T> {
+    /// Plays an identity accelerator: moves everything published on the
+    /// sender over to the receiver.
+    pub(crate) fn loopback(&self) {
+        self.loopback_with(|elem| elem);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: Copy, R: Copy> Drop for Cohort<S, R> {
+    fn drop(&mut self) {
+        //TODO: This drop function doesn't seem to work
+        // and we are forced to re-boot the system 
+        // everytime we want to connect to cohort again
+
+        // we need to figure out how to make it analagous to the code here:
+        // https://github.com/pengwing-project/cohort-private/blob/cohort/piton/verif/diag/c/riscv/ariane/cohort_linux/cohort_aes_base.c
+
+        // Maybe it's just an issue with how it's used in Demikernel?
+        // Need to test this
+        if !self.registered.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        if let Err(err) = self.cohort_mn_unregister() {
+            (self.on_unregister_error)(self._id, err);
+        }
+    }
+}
+
+/// Parses the id that ends the file name of a device such as `/dev/cohort3`.
+#[cfg(feature = "std")]
+fn device_id(path: &Path) -> Option<u8> {
+    let name = path.file_name()?.to_str()?;
+    let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    name[prefix.len()..].parse().ok()
+}
+
+/// Reports a failed operation on the placeholder queue of a sink or source
+/// cohort as [`Error::DirectionDisabled`] instead of a full or empty queue.
+#[cfg(feature = "std")]
+fn disabled_or<T: Copy>(fifo: &CohortFifo<T>, err: Error) -> Error {
+    if fifo.capacity() == 0 {
+        Error::DirectionDisabled
+    } else {
+        err
+    }
+}
+
+/// The default [`CohortBuilder::on_unregister_error`] callback.
+#[cfg(feature = "std")]
+pub(crate) fn log_unregister_error(id: u8, err: Error) {
+    eprintln!("cohort {id}: {err}");
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::alloc::Layout;
+    use core::pin::Pin;
+    use core::ptr::NonNull;
+    use core::sync::atomic::Ordering;
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    use super::{Aligned, Cohort, CohortBuilder, CohortFifo};
+    use crate::error::Error;
+    use crate::sys::stub::{self, Call};
+
+    #[test]
+    fn drop_unregisters_own_id() {
+        let first = unsafe { Cohort::<u64>::register(1, 8, 2) }.unwrap();
+        let second = unsafe { Cohort::<u64>::register(2, 8, 2) }.unwrap();
+
+        drop(second);
+        drop(first);
+
+        let unregistered: Vec<_> = stub::calls()
+            .into_iter()
+            .filter(|call| matches!(call, Call::Unregister { .. }))
+            .collect();
+        assert_eq!(
+            unregistered,
+            [Call::Unregister { id: 2 }, Call::Unregister { id: 1 }]
+        );
+    }
+
+    #[test]
+    fn lengths_track_both_ends() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 4) }.unwrap();
+        unsafe { cohort.push(&1, &2) };
+        assert_eq!(cohort.sender_len(), 2);
+        assert!(!cohort.sender_is_empty());
+        cohort.flush();
+        while cohort.sender.accel_pop().is_some() {}
+        assert!(cohort.sender_is_empty());
+
+        assert!(cohort.receiver_is_empty());
+        for n in 0..3 {
+            cohort.receiver.accel_push(n).unwrap();
+        }
+        // Nothing has been popped, yet the receiver sees what the accelerator published.
+        assert_eq!(cohort.receiver_len(), 3);
+        assert!(!cohort.receiver_is_empty());
+    }
+
+    #[test]
+    fn free_space_shrinks_per_pair() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 4) }.unwrap();
+        assert_eq!(cohort.sender_free_space(), 8);
+        for pushed in 1..=4 {
+            unsafe { cohort.push(&1, &2) };
+            assert_eq!(cohort.sender_free_space(), 8 - 2 * pushed);
+        }
+        assert!(matches!(
+            unsafe { cohort.try_push(&1, &2) },
+            Err(Error::Full)
+        ));
+
+        while cohort.sender.accel_pop().is_some() {}
+        assert_eq!(cohort.sender_free_space(), 8);
+    }
+
+    #[test]
+    fn pump_makes_partial_progress() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 4, 2) }.unwrap();
+        let input = [(1, 2), (3, 4), (5, 6), (7, 8)];
+        let mut output = [(0, 0); 4];
+
+        // Only two pairs fit, and nothing has come back yet.
+        assert_eq!(unsafe { cohort.pump(3, &input, 4, &mut output) }, (2, 0));
+        cohort.loopback();
+        assert_eq!(
+            unsafe { cohort.pump(0, &input[2..], 1, &mut output) },
+            (0, 1)
+        );
+        assert_eq!(output[0], (1, 2));
+
+        assert_eq!(
+            unsafe { cohort.pump(4, &input[2..], 4, &mut output[1..]) },
+            (2, 1)
+        );
+        cohort.loopback();
+        assert_eq!(unsafe { cohort.pump(4, &[], 4, &mut output[2..]) }, (0, 2));
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn register_rejects_invalid_config() {
+        assert!(matches!(
+            unsafe { Cohort::<u64>::register(0, 3, 2) },
+            Err(Error::CapacityNotEven(3))
+        ));
+        assert!(matches!(
+            unsafe { Cohort::<u64>::register(0, 8, 1) },
+            Err(Error::BatchSizeTooSmall)
+        ));
+        assert!(matches!(
+            unsafe { Cohort::<u64>::register(0, 4, 8) },
+            Err(Error::CapacityLessThanBatchSize)
+        ));
+        // The kernel never hears about a cohort that failed validation.
+        assert!(stub::calls().is_empty());
+    }
+
+    #[test]
+    fn odd_stream_recovers_last_element() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 2) }.unwrap();
+        unsafe { cohort.push(&0, &1) };
+        unsafe { cohort.push(&2, &3) };
+        unsafe { cohort.push_one(&4) };
+        cohort.flush();
+        cohort.loopback();
+
+        let (mut a, mut b) = (0, 0);
+        let mut popped = Vec::new();
+        while unsafe { cohort.try_pop(&mut a, &mut b) }.is_ok() {
+            popped.extend([a, b]);
+        }
+        assert_eq!(cohort.receiver_len(), 1);
+        unsafe { cohort.try_pop_one(&mut a) }.unwrap();
+        popped.push(a);
+        assert_eq!(popped, [0, 1, 2, 3, 4]);
+        assert!(cohort.receiver_is_empty());
+    }
+
+    #[test]
+    fn pop_timeout_gives_up() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 2) }.unwrap();
+        let (mut a, mut b) = (0, 0);
+        assert!(matches!(
+            unsafe { cohort.pop_timeout(&mut a, &mut b, Duration::from_millis(1)) },
+            Err(Error::Empty)
+        ));
+
+        unsafe { cohort.push(&1, &2) };
+        cohort.loopback();
+        assert!(unsafe { cohort.pop_timeout(&mut a, &mut b, Duration::from_millis(1)) }.is_ok());
+        assert_eq!((a, b), (1, 2));
+    }
+
+    #[test]
+    fn request_response_times_out_without_an_answer() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 4) }.unwrap();
+        let (mut a, mut b) = (0, 0);
+        let result = unsafe {
+            cohort.request_response((&1, &2), (&mut a, &mut b), Duration::from_millis(1))
+        };
+        assert_eq!(result, Err(Error::Empty));
+        // Flushed even though the batch is not full.
+        assert_eq!(cohort.sender().available(), 2);
+    }
+
+    #[test]
+    fn pop_or_default_fills_in_when_empty() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 2) }.unwrap();
+        let (mut a, mut b) = (5, 6);
+        assert!(!unsafe { cohort.pop_or_default(&mut a, &mut b) });
+        assert_eq!((a, b), (0, 0));
+
+        unsafe { cohort.push(&1, &2) };
+        cohort.loopback();
+        assert!(unsafe { cohort.pop_or_default(&mut a, &mut b) });
+        assert_eq!((a, b), (1, 2));
+    }
+
+    #[test]
+    fn pairs_round_trip_as_arrays() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 2) }.unwrap();
+        unsafe { cohort.push_pair(&[7, 8]) };
+        cohort.loopback();
+        let mut out = [0; 2];
+        unsafe { cohort.pop_pair(&mut out) };
+        assert_eq!(out, [7, 8]);
+    }
+
+    #[test]
+    fn asymmetric_types_round_trip() {
+        let cohort = unsafe { Cohort::<[u8; 16], [u8; 8]>::register(0, 8, 2) }.unwrap();
+        unsafe { cohort.push(&[1; 16], &[2; 16]) };
+
+        // An accelerator that answers each 16-byte block with an 8-byte tag.
+        cohort.loopback_with(|block| {
+            let mut tag = [0; 8];
+            tag.copy_from_slice(&block[..8]);
+            tag[0] += 100;
+            tag
+        });
+
+        let (mut tag1, mut tag2) = ([0; 8], [0; 8]);
+        unsafe { cohort.pop(&mut tag1, &mut tag2) };
+        assert_eq!(tag1, [101, 1, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(tag2, [102, 2, 2, 2, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn debug_summarizes_without_side_effects() {
+        let cohort = unsafe { Cohort::<u64>::register(7, 8, 4) }.unwrap();
+        unsafe { cohort.push(&1, &2) };
+        for n in 0..3 {
+            cohort.receiver.accel_push(n).unwrap();
+        }
+
+        let debug = format!("{cohort:?}");
+        assert_eq!(
+            debug,
+            "Cohort { id: 7, \
+             sender: CohortFifo { head: 0, sw_tail: 2, hw_tail: 0, capacity: 8, batch_size: 4 }, \
+             receiver: CohortFifo { head: 0, sw_tail: 0, hw_tail: 3, capacity: 8, batch_size: 4 }, \
+             custom_data: 0 }"
+        );
+        // Formatting must not have pulled the receiver's sw_tail forward.
+        assert_eq!(format!("{cohort:?}"), debug);
+    }
+
+    #[test]
+    fn custom_data_round_trips() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 2) }.unwrap();
+        assert_eq!(cohort.get_custom_data(), 0);
+        cohort.set_custom_data(0xDEAD_BEEF);
+        assert_eq!(cohort.get_custom_data(), 0xDEAD_BEEF);
+    }
+
+    #[test]
+    fn reports_configuration() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 48, 6) }.unwrap();
+        assert_eq!(cohort.capacity(), 48);
+        assert_eq!(cohort.batch_size(), 6);
+    }
+
+    #[test]
+    fn reset_empties_both_ends() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 2) }.unwrap();
+        unsafe { cohort.push(&1, &2) };
+        cohort.loopback();
+        unsafe { cohort.push(&3, &4) };
+
+        unsafe { cohort.reset() };
+        assert_eq!(cohort.sender_len(), 0);
+        assert!(cohort.receiver_is_empty());
+    }
+
+    #[test]
+    fn try_register_reports_rejection() {
+        stub::fail_next(libc::EBUSY);
+        assert!(matches!(
+            unsafe { Cohort::<u64>::try_register(6, 8, 2) },
+            Err(Error::RegistrationFailed(libc::EBUSY))
+        ));
+        // A rejected cohort is never unregistered, which could tear down
+        // whoever does own the id.
+        assert_eq!(stub::calls(), [Call::Register { backoff: 240 }]);
+
+        stub::fail_next(libc::EBUSY);
+        let cohort = unsafe { Cohort::<u64>::register(6, 8, 2) };
+        assert!(cohort.is_ok());
+    }
+
+    #[test]
+    fn extend_flushes_trailing_batch() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 256, 8) }.unwrap();
+        let pairs: Vec<_> = (0..100).map(|n| (2 * n, 2 * n + 1)).collect();
+
+        unsafe { cohort.extend_from_slice(&pairs[..50]) };
+        unsafe { cohort.extend(pairs[50..].to_vec()) };
+        cohort.loopback();
+
+        let drained: Vec<_> = unsafe { cohort.drain() }.collect();
+        assert_eq!(drained, (0..200).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn pop_batch_waits_for_output_batch() {
+        let cohort = unsafe {
+            CohortBuilder::new()
+                .capacity(16)
+                .batch_size(8)
+                .out_batch_size(4)
+                .build::<u64, u64>()
+        }
+        .unwrap();
+        assert_eq!((cohort.batch_size(), cohort.out_batch_size()), (8, 4));
+
+        for n in 0..4 {
+            unsafe { cohort.push(&(2 * n), &(2 * n + 1)) };
+        }
+        // Only the first of these is a whole output batch once looped back.
+        for _ in 0..6 {
+            let elem = cohort.sender.accel_pop().unwrap();
+            cohort.receiver.accel_push(elem).unwrap();
+        }
+
+        let mut out = [0; 8];
+        assert_eq!(unsafe { cohort.pop_batch(&mut out) }, 4);
+        assert_eq!(out[..4], [0, 1, 2, 3]);
+
+        cohort.loopback();
+        assert_eq!(unsafe { cohort.pop_batch(&mut out) }, 4);
+        assert_eq!(out[..4], [4, 5, 6, 7]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn register_on_cpu_pins_thread() {
+        let cohort = unsafe { Cohort::<u64>::register_on_cpu(0, 8, 2, 0) }.unwrap();
+        assert_eq!(stub::calls(), [Call::Register { backoff: 240 }]);
+
+        let mut set: libc::cpu_set_t = unsafe { core::mem::zeroed() };
+        let ret = unsafe {
+            libc::sched_getaffinity(0, core::mem::size_of::<libc::cpu_set_t>(), &mut set)
+        };
+        assert_eq!(ret, 0);
+        assert!(unsafe { libc::CPU_ISSET(0, &set) });
+        assert_eq!(unsafe { libc::CPU_COUNT(&set) }, 1);
+        drop(cohort);
+
+        assert!(matches!(
+            unsafe { Cohort::<u64>::register_on_cpu(1, 8, 2, usize::MAX) },
+            Err(Error::AffinityFailed(libc::EINVAL))
+        ));
+        // Nothing is registered when pinning fails.
+        assert_eq!(
+            stub::calls(),
+            [Call::Register { backoff: 240 }, Call::Unregister { id: 0 }]
+        );
+    }
+
+    fn verify_cohort() -> Pin<Box<Cohort<u64>>> {
+        unsafe {
+            CohortBuilder::new()
+                .capacity(16)
+                .batch_size(2)
+                .verify(true)
+                .build::<u64, u64>()
+        }
+        .unwrap()
+    }
+
+    #[test]
+    fn verify_catches_counted_pair_that_is_dropped() {
+        let cohort = verify_cohort();
+        let (mut a, mut b) = (0, 0);
+
+        unsafe { cohort.push(&1, &2) };
+        cohort.loopback();
+        assert_eq!(cohort.get_custom_data(), 2);
+        unsafe { cohort.try_pop(&mut a, &mut b) }.unwrap();
+
+        for n in 0..3 {
+            unsafe { cohort.push(&n, &n) };
+        }
+        // An accelerator that takes the first of these pairs and counts it,
+        // as verify mode asks of every element taken, but loses its answer.
+        cohort.sender.accel_pop().unwrap();
+        cohort.sender.accel_pop().unwrap();
+        cohort.custom_data.0.fetch_add(2, Ordering::Release);
+        cohort.loopback();
+
+        assert!(matches!(
+            unsafe { cohort.try_pop(&mut a, &mut b) },
+            Err(Error::SequenceMismatch {
+                expected: 8,
+                got: 6
+            })
+        ));
+    }
+
+    #[test]
+    fn verify_allows_pairs_in_flight() {
+        let cohort = verify_cohort();
+        let (mut a, mut b) = (0, 0);
+
+        unsafe { cohort.push(&1, &2) };
+        unsafe { cohort.push(&3, &4) };
+        // Answer only the first pair.
+        for _ in 0..2 {
+            let elem = cohort.sender.accel_pop().unwrap();
+            cohort.receiver.accel_push(elem).unwrap();
+        }
+        cohort.custom_data.0.fetch_add(2, Ordering::Release);
+
+        unsafe { cohort.try_pop(&mut a, &mut b) }.unwrap();
+        assert_eq!((a, b), (1, 2));
+        assert!(matches!(
+            unsafe { cohort.try_pop(&mut a, &mut b) },
+            Err(Error::Empty)
+        ));
+
+        cohort.loopback();
+        let mut out = [0; 4];
+        assert_eq!(unsafe { cohort.pop_slice(&mut out) }, 2);
+        assert_eq!(out[..2], [3, 4]);
+    }
+
+    #[test]
+    fn verify_catches_duplicated_element() {
+        let cohort = verify_cohort();
+        let (mut a, mut b) = (0, 0);
+
+        unsafe { cohort.try_push_one(&7) }.unwrap();
+        assert_eq!(unsafe { cohort.try_push_slice(&[8]) }, 1);
+        cohort.flush();
+        cohort.loopback();
+        // An accelerator that answers the last element twice.
+        cohort.receiver.accel_push(8).unwrap();
+        cohort.custom_data.0.fetch_add(1, Ordering::Release);
+
+        unsafe { cohort.try_pop(&mut a, &mut b) }.unwrap();
+        assert!(matches!(
+            unsafe { cohort.try_pop_one(&mut a) },
+            Err(Error::SequenceMismatch {
+                expected: 2,
+                got: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn from_mapped_registers_adopted_buffers() {
+        let mut sender = Aligned([0_u64; 9]);
+        let mut receiver = Aligned([0_u64; 9]);
+        let cohort = unsafe {
+            Cohort::<u64>::from_mapped(
+                2,
+                NonNull::from(&mut sender.0).cast(),
+                NonNull::from(&mut receiver.0).cast(),
+                8,
+                2,
+            )
+        }
+        .unwrap();
+        assert_eq!(stub::calls(), [Call::Register { backoff: 240 }]);
+
+        unsafe { cohort.push(&5, &6) };
+        cohort.loopback();
+        let (mut a, mut b) = (0, 0);
+        unsafe { cohort.try_pop(&mut a, &mut b) }.unwrap();
+        assert_eq!((a, b), (5, 6));
+        drop(cohort);
+
+        // The pair went through the mapped buffers.
+        assert_eq!(sender.0[..2], [5, 6]);
+        assert_eq!(receiver.0[..2], [5, 6]);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats_count_full_rejections() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 4, 2) }.unwrap();
+        unsafe { cohort.push(&1, &2) };
+        unsafe { cohort.push(&3, &4) };
+        assert!(matches!(
+            unsafe { cohort.try_push(&5, &6) },
+            Err(Error::Full)
+        ));
+        assert!(matches!(
+            unsafe { cohort.try_push(&5, &6) },
+            Err(Error::Full)
+        ));
+
+        let stats = cohort.stats();
+        assert_eq!(stats.push_count, 4);
+        assert_eq!(stats.full_rejections, 2);
+        assert_eq!(stats.push_spins, 0);
+
+        // Pushing onto a source cohort is not a full queue.
+        let source = unsafe { Cohort::<u64>::register_source(1, 4, 2) }.unwrap();
+        assert!(matches!(
+            unsafe { source.try_push(&1, &2) },
+            Err(Error::DirectionDisabled)
+        ));
+        assert_eq!(source.stats().full_rejections, 0);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats_count_every_path() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 2) }.unwrap();
+        unsafe { cohort.push_one(&1) };
+        unsafe { cohort.push_n(&[2, 3]) };
+        unsafe { cohort.push_slice(&[4, 5]) };
+        assert_eq!(unsafe { cohort.try_push_slice(&[6, 7, 8, 9]) }, 3);
+        assert!(matches!(
+            unsafe { cohort.try_push_one(&10) },
+            Err(Error::Full)
+        ));
+        cohort.flush();
+        cohort.loopback();
+
+        let mut elem = 0;
+        unsafe { cohort.pop_one(&mut elem) };
+        assert_eq!(unsafe { cohort.pop_n::<2>() }, [2, 3]);
+        unsafe { cohort.try_pop_exact(&mut [0; 2]) }.unwrap();
+        assert_eq!(unsafe { cohort.pop_slice(&mut [0; 2]) }, 2);
+        assert_eq!(unsafe { cohort.drain() }.count(), 1);
+        assert!(matches!(
+            unsafe { cohort.try_pop_one(&mut elem) },
+            Err(Error::Empty)
+        ));
+
+        let stats = cohort.stats();
+        assert_eq!((stats.push_count, stats.full_rejections), (8, 1));
+        assert_eq!((stats.pop_count, stats.empty_rejections), (8, 1));
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn reset_stats_starts_from_zero() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 2) }.unwrap();
+        unsafe { cohort.push(&1, &2) };
+        cohort.loopback();
+        let (mut a, mut b) = (0, 0);
+        unsafe { cohort.pop(&mut a, &mut b) };
+        assert!(matches!(
+            unsafe { cohort.try_pop(&mut a, &mut b) },
+            Err(Error::Empty)
+        ));
+
+        let before = cohort.stats();
+        assert_eq!(cohort.reset_stats(), before);
+        assert_eq!(
+            (before.push_count, before.pop_count, before.empty_rejections),
+            (2, 2, 1)
+        );
+        assert_eq!(cohort.stats(), Default::default());
+
+        unsafe { cohort.push(&3, &4) };
+        assert_eq!(cohort.stats().push_count, 2);
+    }
+
+    #[test]
+    fn bounded_calls_give_up_after_max_spins() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 2, 2) }.unwrap();
+        let (mut a, mut b) = (0, 0);
+        assert!(matches!(
+            unsafe { cohort.pop_bounded(&mut a, &mut b, 100) },
+            Err(Error::Empty)
+        ));
+
+        // No attempts at all: nothing is sent even though there is room.
+        assert!(matches!(
+            unsafe { cohort.push_bounded(&1, &2, 0) },
+            Err(Error::Full)
+        ));
+        assert!(cohort.sender_is_empty());
+        unsafe { cohort.push_bounded(&1, &2, 1) }.unwrap();
+        assert!(matches!(
+            unsafe { cohort.push_bounded(&3, &4, 100) },
+            Err(Error::Full)
+        ));
+
+        cohort.loopback();
+        assert!(matches!(
+            unsafe { cohort.pop_bounded(&mut a, &mut b, 0) },
+            Err(Error::Empty)
+        ));
+        assert_eq!(cohort.receiver_len(), 2);
+        unsafe { cohort.pop_bounded(&mut a, &mut b, 1) }.unwrap();
+        assert_eq!((a, b), (1, 2));
+    }
+
+    #[test]
+    fn register_retries_until_accepted() {
+        stub::fail_next(libc::EBUSY);
+        stub::fail_next(libc::EBUSY);
+        let cohort = unsafe { Cohort::<u64>::try_register_with_retry(3, 8, 2, 3, Duration::ZERO) };
+        assert!(cohort.is_ok());
+        assert_eq!(stub::calls(), [Call::Register { backoff: 240 }; 3]);
+
+        stub::fail_next(libc::EBUSY);
+        stub::fail_next(libc::EAGAIN);
+        let cohort = unsafe { Cohort::<u64>::try_register_with_retry(4, 8, 2, 2, Duration::ZERO) };
+        assert_eq!(cohort.unwrap_err(), Error::RegistrationFailed(libc::EAGAIN));
+    }
+
+    #[test]
+    fn from_fifos_wires_own_queues() {
+        let sender = CohortFifo::<u64>::new(8, 4).unwrap();
+        let receiver = CohortFifo::<u64>::new(16, 2).unwrap();
+        let sender_buffer = sender.buffer_ptr();
+        let cohort = unsafe { Cohort::from_fifos(5, sender, receiver) }.unwrap();
+        assert_eq!(stub::calls(), [Call::Register { backoff: 240 }]);
+        assert_eq!(cohort.buffer_ptrs().0, sender_buffer);
+        assert_eq!((cohort.batch_size(), cohort.out_batch_size()), (4, 2));
+
+        unsafe { cohort.push(&1, &2) };
+        unsafe { cohort.push(&3, &4) };
+        cohort.loopback();
+        let (mut a, mut b) = (0, 0);
+        unsafe { cohort.pop(&mut a, &mut b) };
+        assert_eq!((a, b), (1, 2));
+        unsafe { cohort.pop(&mut a, &mut b) };
+        assert_eq!((a, b), (3, 4));
+    }
+
+    #[test]
+    fn batch_push_publishes_whole_batch() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 4) }.unwrap();
+        assert_eq!(
+            unsafe { cohort.batch_push(&[(1, 2)]) },
+            Err(Error::BatchLengthMismatch {
+                expected: 2,
+                got: 1
+            })
+        );
+
+        unsafe { cohort.batch_push(&[(1, 2), (3, 4)]) }.unwrap();
+        assert_eq!(cohort.sender_snapshot().hw_tail, 4);
+        unsafe { cohort.batch_push(&[(5, 6), (7, 8)]) }.unwrap();
+        assert_eq!(cohort.sender_snapshot().hw_tail, 8);
+        assert_eq!(
+            unsafe { cohort.batch_push(&[(9, 10), (11, 12)]) },
+            Err(Error::Full)
+        );
+        assert_eq!(cohort.sender_snapshot().sw_tail, 8);
+    }
+
+    #[test]
+    fn push_from_iter_counts_and_flushes() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 256, 16) }.unwrap();
+        let pushed = unsafe { cohort.push_from_iter((0..100).map(|i| (i, i * i))) };
+        assert_eq!(pushed, 100);
+        // The last partial batch was flushed too.
+        assert_eq!(cohort.sender_snapshot().hw_tail, 200);
+
+        cohort.loopback();
+        let expected: Vec<_> = (0..100).map(|i| (i, i * i)).collect();
+        assert_eq!(unsafe { cohort.drain_into_vec() }, expected);
+    }
+
+    #[test]
+    fn push_mapped_packs_the_pair() {
+        let cohort = unsafe { Cohort::<[u8; 8]>::register(0, 8, 2) }.unwrap();
+        let split = |x: u32| ([x.to_be_bytes(), [0; 4]].concat().try_into().unwrap(), [0xFF; 8]);
+        unsafe { cohort.push_mapped(0x0102_0304, split) };
+
+        cohort.loopback();
+        let (mut a, mut b) = ([0; 8], [0; 8]);
+        unsafe { cohort.pop(&mut a, &mut b) };
+        assert_eq!(a, [1, 2, 3, 4, 0, 0, 0, 0]);
+        assert_eq!(b, [0xFF; 8]);
+    }
+
+    #[test]
+    fn new_waits_for_activate() {
+        let cohort = Cohort::<u64>::new(6, 8, 2).unwrap();
+        cohort.set_custom_data(0xC0FFEE);
+        unsafe { cohort.push(&1, &2) };
+        assert!(cohort.buffer_ptrs().0.addr().is_multiple_of(128));
+        assert!(stub::calls().is_empty());
+
+        unsafe { cohort.as_ref().activate() }.unwrap();
+        unsafe { cohort.as_ref().activate() }.unwrap();
+        assert_eq!(stub::calls(), [Call::Register { backoff: 240 }]);
+        assert_eq!(cohort.get_custom_data(), 0xC0FFEE);
+    }
+
+    #[test]
+    fn registering_twice_is_refused() {
+        let cohort = unsafe { Cohort::<u64>::register(2, 8, 2) }.unwrap();
+        assert!(cohort.is_registered());
+        assert_eq!(cohort.cohort_mn_register(), Err(Error::AlreadyRegistered));
+        drop(cohort);
+        assert_eq!(
+            stub::calls(),
+            [Call::Register { backoff: 240 }, Call::Unregister { id: 2 }]
+        );
+
+        // A rejected registration leaves the cohort free to try again, and
+        // nothing to unregister.
+        let cohort = Cohort::<u64>::new(3, 8, 2).unwrap();
+        stub::fail_next(libc::EBUSY);
+        assert!(unsafe { cohort.as_ref().activate() }.is_err());
+        assert!(!cohort.is_registered());
+        unsafe { cohort.as_ref().activate() }.unwrap();
+        assert!(cohort.is_registered());
+    }
+
+    #[test]
+    fn buffer_ptrs_point_at_queues() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 2) }.unwrap();
+        unsafe { cohort.push(&1, &2) };
+        let (sender, receiver) = cohort.buffer_ptrs();
+        assert!(receiver.addr().is_multiple_of(128));
+        assert_eq!(unsafe { *sender.add(1) }, 2);
+    }
+
+    #[test]
+    fn memory_footprint_matches_layouts() {
+        let cohort = unsafe { Cohort::<[u8; 8]>::register(0, 64, 8) }.unwrap();
+        let buffer = Layout::array::<[u8; 8]>(65)
+            .unwrap()
+            .align_to(128)
+            .unwrap()
+            .pad_to_align()
+            .size();
+        assert_eq!(cohort.memory_footprint(), 2 * buffer + Layout::for_value(&*cohort).size());
+    }
+
+    #[test]
+    fn sink_and_source_carry_one_direction() {
+        let full = unsafe { Cohort::<u64>::register(0, 64, 8) }.unwrap();
+        let sink = unsafe { Cohort::<u64>::register_sink(1, 64, 8) }.unwrap();
+        let source = unsafe { Cohort::<u64>::register_source(2, 64, 8) }.unwrap();
+        // The placeholder queue takes a single 128-byte block instead of a buffer.
+        let buffer = full.sender().capacity_in_bytes();
+        assert_eq!(
+            sink.memory_footprint(),
+            full.memory_footprint() - buffer + 128
+        );
+        assert_eq!(sink.memory_footprint(), source.memory_footprint());
+
+        unsafe { sink.push(&1, &2) };
+        sink.flush();
+        assert_eq!(sink.sender_len(), 2);
+        let (mut a, mut b) = (0, 0);
+        assert_eq!(
+            unsafe { sink.try_pop(&mut a, &mut b) },
+            Err(Error::DirectionDisabled)
+        );
+        assert_eq!(
+            unsafe { sink.try_pop_one(&mut a) },
+            Err(Error::DirectionDisabled)
+        );
+        assert_eq!(sink.peek(&mut a, &mut b), Err(Error::DirectionDisabled));
+
+        assert_eq!(
+            unsafe { source.try_push(&1, &2) },
+            Err(Error::DirectionDisabled)
+        );
+        assert_eq!(
+            unsafe { source.try_push_one(&1) },
+            Err(Error::DirectionDisabled)
+        );
+        source.receiver().accel_push(3).unwrap();
+        source.receiver().accel_push(4).unwrap();
+        unsafe { source.pop(&mut a, &mut b) };
+        assert_eq!((a, b), (3, 4));
+        assert_eq!(unsafe { source.try_pop(&mut a, &mut b) }, Err(Error::Empty));
+    }
+
+    #[test]
+    #[should_panic(expected = "does not carry data")]
+    fn blocking_push_on_source_panics() {
+        let source = unsafe { Cohort::<u64>::register_source(0, 64, 8) }.unwrap();
+        unsafe { source.push(&1, &2) };
+    }
+
+    #[test]
+    fn register_device_reads_id_from_name() {
+        let dir = std::env::temp_dir().join(format!("cohort-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let device = dir.join("cohort9");
+        std::fs::write(&device, b"").unwrap();
+
+        let cohort = unsafe { Cohort::<u64>::register_device(&device, 8, 2) }.unwrap();
+        drop(cohort);
+        assert_eq!(
+            stub::calls(),
+            [Call::Register { backoff: 240 }, Call::Unregister { id: 9 }]
+        );
+
+        let unnamed = dir.join("cohort");
+        std::fs::write(&unnamed, b"").unwrap();
+        for path in [dir.join("cohort10"), unnamed] {
+            assert!(matches!(
+                unsafe { Cohort::<u64>::register_device(&path, 8, 2) },
+                Err(Error::DeviceNotFound)
+            ));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn try_push_all_returns_remainder() {
+        let cohort = unsafe { Cohort::<u64>::register(0, 8, 8) }.unwrap();
+        unsafe { cohort.push(&0, &0) };
+        unsafe { cohort.push(&0, &0) };
+
+        let pairs: Vec<_> = (1..=3).map(|n| (n, n)).collect();
+        let rest = unsafe { cohort.try_push_all(&pairs) };
+        assert_eq!(rest, &pairs[2..]);
+        assert_eq!(cohort.sender_len(), 8);
+        while cohort.sender.accel_pop().is_some() {}
+
+        // A burst that fits in less than a batch is still flushed.
+        assert!(unsafe { cohort.try_push_all(&pairs[2..]) }.is_empty());
+        assert_eq!(cohort.sender.accel_pop(), Some(3));
+    }
+
+    #[test]
+    fn register_many_is_all_or_nothing() {
+        let pool = unsafe { Cohort::<u64>::register_many(&[1, 2], 8, 2) }.unwrap();
+        assert_eq!(pool.len(), 2);
+        drop(pool);
+        let before = stub::calls().len();
+
+        stub::succeed_next();
+        stub::succeed_next();
+        stub::fail_next(libc::EBUSY);
+        assert!(matches!(
+            unsafe { Cohort::<u64>::register_many(&[1, 2, 3, 4], 8, 2) },
+            Err(Error::RegistrationFailed(libc::EBUSY))
+        ));
+        let register = Call::Register { backoff: 240 };
+        assert_eq!(
+            stub::calls()[before..],
+            [
+                register,
+                register,
+                register,
+                Call::Unregister { id: 1 },
+                Call::Unregister { id: 2 }
+            ]
+        );
+    }
+
+    #[test]
+    fn unregister_surfaces_errno() {
+        let cohort = unsafe { Cohort::<u64>::register(3, 8, 2) }.unwrap();
+
+        stub::fail_next(libc::EINVAL);
+        assert!(matches!(
+            cohort.unregister(),
+            Err(Error::UnregisterFailed(libc::EINVAL))
+        ));
+        // The explicit unregister replaces the one on drop.
+        assert_eq!(
+            stub::calls(),
+            [Call::Register { backoff: 240 }, Call::Unregister { id: 3 }]
+        );
+
+        let cohort = unsafe { Cohort::<u64>::register(4, 8, 2) }.unwrap();
+        assert_eq!(cohort.unregister(), Ok(()));
+        assert_eq!(
+            stub::calls()[2..],
+            [Call::Register { backoff: 240 }, Call::Unregister { id: 4 }]
+        );
+    }
+
+    #[test]
+    fn drop_reports_unregister_failure() {
+        thread_local! {
+            static FAILURES: RefCell<Vec<(u8, Error)>> = const { RefCell::new(Vec::new()) };
+        }
+        fn record(id: u8, err: Error) {
+            FAILURES.with(|failures| failures.borrow_mut().push((id, err)));
+        }
+
+        let cohort = unsafe {
+            CohortBuilder::new()
+                .id(7)
+                .capacity(8)
+                .batch_size(2)
+                .on_unregister_error(record)
+                .build::<u64, u64>()
+        }
+        .unwrap();
+
+        stub::fail_next(libc::EINVAL);
+        drop(cohort);
+        FAILURES.with(|failures| {
+            assert!(matches!(
+                failures.borrow()[..],
+                [(7, Error::UnregisterFailed(libc::EINVAL))]
+            ));
+        });
     }
 }
@@ -0,0 +1,295 @@
+//! A software accelerator for exercising cohorts without Cohort hardware.
+//!
+//! Enabled by the `mock` feature.
+
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::builder::CohortBuilder;
+use crate::error::Result;
+use crate::Cohort;
+
+/// Moves everything pushed onto a cohort back to its receiving end from a
+/// background thread, like an accelerator would.
+///
+/// The cohort is created from a [`CohortBuilder`] but never registered with
+/// the kernel, so end-to-end tests of push/pop logic run on any machine. The
+/// thread plays the accelerator through the same queue pointers the
+/// hardware would use: it consumes elements once they are published through
+/// the sender's hw_tail, passes each through the transform, and publishes
+/// the results on the receiver's hw_tail, signalling the cohort's
+/// [`readiness_fd`](Cohort::readiness_fd) each time it does.
+///
+/// Dropping the accelerator stops and joins the thread.
+///
+/// ```
+/// # use cohort::{CohortBuilder, LoopbackAccelerator};
+/// let accel = LoopbackAccelerator::<u64>::new(&CohortBuilder::new()).unwrap();
+/// let cohort = accel.cohort();
+/// let producer = cohort.producer().unwrap();
+/// producer.push(&1, &2);
+/// producer.flush();
+///
+/// let (mut a, mut b) = (0, 0);
+/// cohort.consumer().unwrap().pop(&mut a, &mut b);
+/// assert_eq!((a, b), (1, 2));
+/// ```
+pub struct LoopbackAccelerator<S: Copy, R: Copy = S> {
+    cohort: Arc<Pin<Box<Cohort<S, R>>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T: Copy + Send + 'static> LoopbackAccelerator<T> {
+    /// Starts an accelerator that hands every element back unchanged.
+    ///
+    /// Fails if the configuration is invalid.
+    pub fn new(config: &CohortBuilder) -> Result<Self> {
+        Self::with_transform(config, |elem| elem)
+    }
+}
+
+impl<S: Copy + Send + 'static, R: Copy + Send + 'static> LoopbackAccelerator<S, R> {
+    /// Starts an accelerator that answers each element with `transform` of it.
+    ///
+    /// Fails if the configuration is invalid.
+    pub fn with_transform(
+        config: &CohortBuilder,
+        mut transform: impl FnMut(S) -> R + Send + 'static,
+    ) -> Result<Self> {
+        let cohort = Arc::new(Cohort::with_config(config)?);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let cohort = cohort.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Acquire) {
+                    if cohort.loopback_with(&mut transform) == 0 {
+                        thread::yield_now();
+                    } else {
+                        #[cfg(target_os = "linux")]
+                        cohort.signal_ready();
+                    }
+                }
+            })
+        };
+
+        Ok(LoopbackAccelerator {
+            cohort,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the cohort the accelerator is serving.
+    pub fn cohort(&self) -> &Cohort<S, R> {
+        &self.cohort
+    }
+}
+
+impl<S: Copy, R: Copy> Drop for LoopbackAccelerator<S, R> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            // Surface a panicking transform, unless we are unwinding already.
+            if thread.join().is_err() && !thread::panicking() {
+                panic!("loopback accelerator panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::LoopbackAccelerator;
+    use crate::error::Error;
+    use crate::sys::stub;
+    use crate::CohortBuilder;
+
+    #[test]
+    fn identity_preserves_order() {
+        const COUNT: u64 = 1000;
+        let config = CohortBuilder::new().capacity(16).batch_size(4);
+        let accel = LoopbackAccelerator::<u64>::new(&config).unwrap();
+        let cohort = accel.cohort();
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in (0..COUNT).step_by(2) {
+                    unsafe { cohort.push(&i, &(i + 1)) };
+                }
+                cohort.flush();
+            });
+
+            for expected in (0..COUNT).step_by(2) {
+                let (mut a, mut b) = (0, 0);
+                unsafe { cohort.pop(&mut a, &mut b) };
+                assert_eq!((a, b), (expected, expected + 1));
+            }
+        });
+        assert!(cohort.receiver_is_empty());
+        assert!(stub::calls().is_empty());
+    }
+
+    #[test]
+    fn wait_sender_drained_sees_consumption() {
+        let config = CohortBuilder::new().capacity(16).batch_size(8);
+        let accel = LoopbackAccelerator::<u64>::new(&config).unwrap();
+        let cohort = accel.cohort();
+
+        // Too few to fill a batch, so only the flush in the wait publishes them.
+        for i in 0..3 {
+            unsafe { cohort.push(&i, &i) };
+        }
+        cohort.wait_sender_drained();
+        assert!(cohort.sender_is_empty());
+
+        for i in 0..3 {
+            let (mut a, mut b) = (0, 0);
+            unsafe { cohort.pop(&mut a, &mut b) };
+            assert_eq!((a, b), (i, i));
+        }
+        assert!(cohort.wait_receiver_empty_timeout(Duration::ZERO));
+    }
+
+    #[test]
+    fn roundtrip_interleaves_through_small_queues() {
+        let config = CohortBuilder::new().capacity(16).batch_size(4);
+        let accel = LoopbackAccelerator::<u64>::new(&config).unwrap();
+        let cohort = accel.cohort();
+
+        let input: Vec<_> = (0..64).map(|i| (i, !i)).collect();
+        let mut output = [(0, 0); 64];
+        unsafe { cohort.roundtrip(&input, &mut output) }.unwrap();
+        assert_eq!(&output[..], &input[..]);
+    }
+
+    #[test]
+    fn roundtrip_drops_answers_that_do_not_fit() {
+        let config = CohortBuilder::new().capacity(16).batch_size(4);
+        let accel = LoopbackAccelerator::<u64>::new(&config).unwrap();
+        let cohort = accel.cohort();
+
+        // Far more answers than the receiver and output hold together.
+        let input: Vec<_> = (0..64).map(|i| (i, !i)).collect();
+        let mut output = [(0, 0); 4];
+        unsafe { cohort.roundtrip(&input, &mut output) }.unwrap();
+        assert_eq!(&output[..], &input[..4]);
+        assert!(cohort.receiver_is_empty());
+
+        // The cohort is left ready for the next round.
+        let mut output = [(0, 0); 2];
+        unsafe { cohort.roundtrip(&input[..2], &mut output) }.unwrap();
+        assert_eq!(&output[..], &input[..2]);
+    }
+
+    #[test]
+    fn drain_into_vec_returns_pairs_in_order() {
+        let config = CohortBuilder::new().capacity(64).batch_size(4);
+        let accel = LoopbackAccelerator::<u64>::new(&config).unwrap();
+        let cohort = accel.cohort();
+
+        let input: Vec<_> = (0..20).map(|i| (i, i + 100)).collect();
+        unsafe { cohort.extend_from_slice(&input) };
+        while cohort.receiver_len() < 2 * input.len() {
+            thread::yield_now();
+        }
+
+        assert_eq!(unsafe { cohort.drain_into_vec() }, input);
+        assert!(unsafe { cohort.drain_into_vec() }.is_empty());
+    }
+
+    #[test]
+    fn request_response_waits_for_the_answer() {
+        // A batch the single request never fills alone.
+        let config = CohortBuilder::new().capacity(16).batch_size(8);
+        let accel = LoopbackAccelerator::with_transform(&config, |x: u64| x * 10).unwrap();
+        let cohort = accel.cohort();
+
+        for i in 1..4 {
+            let (mut a, mut b) = (0, 0);
+            unsafe {
+                cohort.request_response((&i, &(i + 1)), (&mut a, &mut b), Duration::from_secs(10))
+            }
+            .unwrap();
+            assert_eq!((a, b), (i * 10, (i + 1) * 10));
+        }
+    }
+
+    #[test]
+    fn wait_for_response_count_sees_all_responses() {
+        let config = CohortBuilder::new().capacity(32).batch_size(4);
+        let accel = LoopbackAccelerator::with_transform(&config, |x: u64| x + 1).unwrap();
+        let cohort = accel.cohort();
+
+        let requests: Vec<u64> = (0..16).collect();
+        unsafe { cohort.push_slice(&requests) };
+        cohort.flush();
+        cohort
+            .wait_for_response_count(16, Duration::from_secs(10))
+            .unwrap();
+
+        let mut responses = [0; 16];
+        assert_eq!(unsafe { cohort.pop_slice(&mut responses) }, 16);
+        assert!(responses.iter().copied().eq(1..17));
+        assert_eq!(
+            cohort.wait_for_response_count(1, Duration::from_millis(5)),
+            Err(Error::Empty)
+        );
+    }
+
+    #[test]
+    fn pop_window_collects_two_bursts() {
+        let config = CohortBuilder::new().capacity(16).batch_size(4);
+        let accel = LoopbackAccelerator::<u64>::new(&config).unwrap();
+        let cohort = accel.cohort();
+
+        let mut out = [(0, 0); 4];
+        let count = thread::scope(|scope| {
+            scope.spawn(|| {
+                unsafe { cohort.batch_push(&[(1, 2), (3, 4)]) }.unwrap();
+                thread::sleep(Duration::from_millis(20));
+                unsafe { cohort.batch_push(&[(5, 6), (7, 8)]) }.unwrap();
+            });
+            unsafe { cohort.pop_window(&mut out, Duration::from_secs(10)) }
+        });
+        assert_eq!(count, 4);
+        assert_eq!(out, [(1, 2), (3, 4), (5, 6), (7, 8)]);
+
+        // Nothing more is coming, so the window runs out.
+        assert_eq!(
+            unsafe { cohort.pop_window(&mut out, Duration::from_millis(5)) },
+            0
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn readiness_fd_signals_output() {
+        let config = CohortBuilder::new().capacity(16).batch_size(2);
+        let accel = LoopbackAccelerator::<u64>::new(&config).unwrap();
+        let cohort = accel.cohort();
+        let mut poll = libc::pollfd {
+            fd: cohort.readiness_fd().unwrap(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        assert_eq!(unsafe { libc::poll(&mut poll, 1, 0) }, 0);
+
+        unsafe { cohort.push(&1, &2) };
+        assert_eq!(unsafe { libc::poll(&mut poll, 1, 10_000) }, 1);
+        assert_ne!(poll.revents & libc::POLLIN, 0);
+
+        cohort.clear_readiness();
+        let (mut a, mut b) = (0, 0);
+        unsafe { cohort.pop(&mut a, &mut b) };
+        assert_eq!((a, b), (1, 2));
+        assert_eq!(unsafe { libc::poll(&mut poll, 1, 0) }, 0);
+    }
+}
@@ -0,0 +1,130 @@
+//! Moving plain-old-data structs through a cohort.
+//!
+//! Enabled by the `bytemuck` feature.
+
+use core::mem;
+use std::fmt::Write;
+
+use bytemuck::Pod;
+
+use crate::error::{Error, Result};
+use crate::Cohort;
+
+impl<S: Pod, R: Pod> Cohort<S, R> {
+    /// Sends `value` to the accelerator as consecutive `S` words.
+    ///
+    /// Lets `#[repr(C)]` command structs be pushed directly instead of being
+    /// packed into byte arrays by hand. Fails with
+    /// [`Error::UnsupportedElementSize`] unless `P` is a whole number of `S`
+    /// words. May block if the sending end is full.
+    ///
+    /// # Safety
+    ///
+    /// As for [`push`](Cohort::push).
+    pub unsafe fn push_pod<P: Pod>(&self, value: &P) -> Result<()> {
+        check_words::<P, S>()?;
+        for word in bytemuck::bytes_of(value).chunks_exact(mem::size_of::<S>()) {
+            unsafe { self.push_one(&bytemuck::pod_read_unaligned(word)) };
+        }
+        Ok(())
+    }
+
+    /// Receives a `P` from the accelerator, assembled from consecutive `R` words.
+    ///
+    /// Fails with [`Error::UnsupportedElementSize`] unless `P` is a whole
+    /// number of `R` words. May block until enough words have arrived.
+    ///
+    /// # Safety
+    ///
+    /// As for [`pop`](Cohort::pop).
+    pub unsafe fn pop_pod<P: Pod>(&self) -> Result<P> {
+        check_words::<P, R>()?;
+        let mut value = P::zeroed();
+        for chunk in bytemuck::bytes_of_mut(&mut value).chunks_exact_mut(mem::size_of::<R>()) {
+            let mut word = R::zeroed();
+            unsafe { self.pop_one(&mut word) };
+            chunk.copy_from_slice(bytemuck::bytes_of(&word));
+        }
+        Ok(value)
+    }
+
+    /// Prints the pointers and hex contents of the receiver queue.
+    pub fn print_receiver_hex(&self) {
+        self.receiver.print_queue_hex();
+    }
+
+    /// Prints the pointers and hex contents of the sender queue.
+    pub fn print_sender_hex(&self) {
+        self.sender.print_queue_hex();
+    }
+}
+
+/// Checks that a `P` is made of a whole number of `W` words.
+fn check_words<P, W>() -> Result<()> {
+    let size = mem::size_of::<P>();
+    if size == 0 || !size.is_multiple_of(mem::size_of::<W>()) {
+        return Err(Error::UnsupportedElementSize(size));
+    }
+    Ok(())
+}
+
+/// Formats `elems` as space-separated hex words, one per element.
+pub(crate) fn hex<T: Pod>(elems: &[T]) -> String {
+    let mut out = String::new();
+    for (i, elem) in elems.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        for byte in bytemuck::bytes_of(elem) {
+            write!(out, "{byte:02x}").unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::{Pod, Zeroable};
+
+    use super::hex;
+    use crate::error::Error;
+    use crate::Cohort;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Command {
+        opcode: u64,
+        len: u64,
+    }
+
+    // SAFETY: Two u64s with no padding.
+    unsafe impl Zeroable for Command {}
+    unsafe impl Pod for Command {}
+
+    #[test]
+    fn pod_round_trips() {
+        let cohort = unsafe { Cohort::<[u8; 8]>::register(0, 8, 2) }.unwrap();
+        let command = Command {
+            opcode: 3,
+            len: 0x1_0000,
+        };
+
+        unsafe { cohort.push_pod(&command) }.unwrap();
+        cohort.loopback();
+        assert_eq!(unsafe { cohort.pop_pod::<Command>() }.unwrap(), command);
+
+        assert!(matches!(
+            unsafe { cohort.push_pod(&0_u32) },
+            Err(Error::UnsupportedElementSize(4))
+        ));
+        assert_eq!(cohort.sender_len(), 0);
+    }
+
+    #[test]
+    fn hex_formats_each_word() {
+        assert_eq!(
+            hex(&[0x0102_u64.to_be(), u64::MAX]),
+            "0000000000000102 ffffffffffffffff"
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! A cohort that many threads can hold a handle to.
+
+use core::ops::Deref;
+use core::pin::Pin;
+use std::sync::Arc;
+
+use crate::Cohort;
+
+/// A reference-counted handle to a registered [`Cohort`], created by
+/// [`Cohort::share`].
+///
+/// Cloning only bumps the count, and the cohort is unregistered once the
+/// last handle is dropped. Every handle derefs to the cohort, which is still
+/// single producer and single consumer: take a [`Producer`](crate::Producer)
+/// and a [`Consumer`](crate::Consumer) to make sure only one thread pushes
+/// and one pops at a time.
+pub struct SharedCohort<S: Copy, R: Copy = S> {
+    cohort: Arc<Pin<Box<Cohort<S, R>>>>,
+}
+
+impl<S: Copy, R: Copy> Cohort<S, R> {
+    /// Turns the cohort into a handle that can be cloned and sent to other threads.
+    ///
+    /// The cohort keeps its registration; nothing is issued to the kernel.
+    pub fn share(self: Pin<Box<Self>>) -> SharedCohort<S, R> {
+        SharedCohort {
+            cohort: Arc::new(self),
+        }
+    }
+}
+
+impl<S: Copy, R: Copy> Clone for SharedCohort<S, R> {
+    fn clone(&self) -> Self {
+        SharedCohort {
+            cohort: self.cohort.clone(),
+        }
+    }
+}
+
+impl<S: Copy, R: Copy> Deref for SharedCohort<S, R> {
+    type Target = Cohort<S, R>;
+
+    fn deref(&self) -> &Cohort<S, R> {
+        &self.cohort
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::sys::stub::{self, Call};
+    use crate::Cohort;
+
+    #[test]
+    fn clones_share_one_registration() {
+        let shared = unsafe { Cohort::<u64>::try_register(6, 64, 2) }
+            .unwrap()
+            .share();
+        let producer = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let producer = shared.producer().unwrap();
+                for i in (0..32).step_by(2) {
+                    producer.push(&i, &(i + 1));
+                }
+            })
+        };
+        producer.join().unwrap();
+
+        let consumer = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                shared.loopback();
+                unsafe { shared.drain_into_vec() }
+            })
+        };
+        let pairs = consumer.join().unwrap();
+        assert_eq!(
+            pairs,
+            (0..32).step_by(2).map(|i| (i, i + 1)).collect::<Vec<_>>()
+        );
+
+        // The threads' handles are gone, but this one keeps the cohort registered.
+        assert_eq!(stub::calls(), [Call::Register { backoff: 240 }]);
+        drop(shared);
+        assert_eq!(
+            stub::calls(),
+            [Call::Register { backoff: 240 }, Call::Unregister { id: 6 }]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Separate sending and receiving handles for a single cohort.
+
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::pin::Pin;
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::Cohort;
+
+/// The sending half of a [`Cohort`], created by [`Cohort::split`].
+///
+/// The cohort stays registered until both halves have been dropped. The
+/// sender can be moved to another thread but not shared between threads, so
+/// its pushes never race, like those of a [`Producer`](crate::Producer).
+pub struct CohortSender<S: Copy, R: Copy = S> {
+    cohort: Arc<Pin<Box<Cohort<S, R>>>>,
+    // Opts out of Sync.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+/// The receiving half of a [`Cohort`], created by [`Cohort::split`].
+///
+/// The cohort stays registered until both halves have been dropped. Like
+/// the sender, the receiver cannot be shared between threads.
+pub struct CohortReceiver<S: Copy, R: Copy = S> {
+    cohort: Arc<Pin<Box<Cohort<S, R>>>>,
+    // Opts out of Sync.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+pub(crate) fn split<S: Copy, R: Copy>(
+    cohort: Pin<Box<Cohort<S, R>>>,
+) -> (CohortSender<S, R>, CohortReceiver<S, R>) {
+    let cohort = Arc::new(cohort);
+    (
+        CohortSender {
+            cohort: cohort.clone(),
+            _not_sync: PhantomData,
+        },
+        CohortReceiver {
+            cohort,
+            _not_sync: PhantomData,
+        },
+    )
+}
+
+// SAFETY: For the pushes below, split hands out only one sender per cohort
+// and it is only ever used from one thread at a time.
+impl<S: Copy, R: Copy> CohortSender<S, R> {
+    /// Sends an element to the accelerator.
+    ///
+    /// May block if the sending end is full.
+    pub fn push(&self, elem1: &S, elem2: &S) {
+        unsafe { self.cohort.push(elem1, elem2) };
+    }
+
+    /// Sends an element to the accelerator.
+    ///
+    /// Will fail if the sending end is full.
+    pub fn try_push(&self, elem1: &S, elem2: &S) -> Result<()> {
+        unsafe { self.cohort.try_push(elem1, elem2) }
+    }
+
+    /// Sends a single element to the accelerator.
+    ///
+    /// May block if the sending end is full.
+    pub fn push_one(&self, elem: &S) {
+        unsafe { self.cohort.push_one(elem) };
+    }
+
+    /// Sends a single element to the accelerator.
+    ///
+    /// Will fail if the sending end is full.
+    pub fn try_push_one(&self, elem: &S) -> Result<()> {
+        unsafe { self.cohort.try_push_one(elem) }
+    }
+
+    /// Makes every element pushed so far visible to the accelerator.
+    pub fn flush(&self) {
+        self.cohort.flush();
+    }
+}
+
+// SAFETY: As for the sender, for the pops below.
+impl<S: Copy, R: Copy> CohortReceiver<S, R> {
+    /// Receives an element from the accelerator.
+    ///
+    /// May block if the receiving end is empty.
+    pub fn pop(&self, elem1: &mut R, elem2: &mut R) {
+        unsafe { self.cohort.pop(elem1, elem2) };
+    }
+
+    /// Receives an element from the accelerator.
+    ///
+    /// Will fail if the receiving end is empty.
+    pub fn try_pop(&self, elem1: &mut R, elem2: &mut R) -> Result<()> {
+        unsafe { self.cohort.try_pop(elem1, elem2) }
+    }
+
+    /// Receives a single element from the accelerator.
+    ///
+    /// May block if the receiving end is empty.
+    pub fn pop_one(&self, elem: &mut R) {
+        unsafe { self.cohort.pop_one(elem) };
+    }
+
+    /// Receives a single element from the accelerator.
+    ///
+    /// Will fail if the receiving end is empty.
+    pub fn try_pop_one(&self, elem: &mut R) -> Result<()> {
+        unsafe { self.cohort.try_pop_one(elem) }
+    }
+
+    /// Receives as many elements from the accelerator as are available into `out`.
+    ///
+    /// Returns the number of elements received.
+    pub fn pop_slice(&self, out: &mut [R]) -> usize {
+        unsafe { self.cohort.pop_slice(out) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::sys::stub::{self, Call};
+    use crate::Cohort;
+
+    #[test]
+    fn sender_moves_to_another_thread() {
+        const THROUGHPUT: u64 = 10_000;
+        let cohort = unsafe { Cohort::<u64>::register(5, 16, 2) }.unwrap();
+        let (sender, receiver) = cohort.split();
+
+        let producer = thread::spawn(move || {
+            for i in (0..THROUGHPUT).step_by(2) {
+                sender.push(&i, &(i + 1));
+            }
+            sender
+        });
+
+        let mut expected = 0;
+        while expected < THROUGHPUT {
+            // Play the accelerator on this thread between pops.
+            receiver.cohort.loopback();
+            let (mut a, mut b) = (0, 0);
+            while receiver.try_pop(&mut a, &mut b).is_ok() {
+                assert_eq!((a, b), (expected, expected + 1));
+                expected += 2;
+            }
+            thread::yield_now();
+        }
+
+        drop(producer.join().unwrap());
+        assert!(!stub::calls().contains(&Call::Unregister { id: 5 }));
+        drop(receiver);
+        assert!(stub::calls().contains(&Call::Unregister { id: 5 }));
+    }
+}
@@ -0,0 +1,133 @@
+//! Counters of how often a cohort's pushes and pops succeed, spin and fail.
+//!
+//! The counters are only kept with the `stats` feature. Without it the
+//! cohort holds a zero-sized stand-in whose methods compile to nothing, so
+//! the hot paths pay nothing for them.
+
+#[cfg(feature = "stats")]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// A snapshot of a cohort's counters, from [`Cohort::stats`](crate::Cohort::stats).
+///
+/// Every push and pop method of the cohort is counted, including those of
+/// its split halves, tokens and byte streams, in elements rather than
+/// pairs. Only pushing onto [`Cohort::sender`](crate::Cohort::sender) or
+/// popping from [`Cohort::receiver`](crate::Cohort::receiver) directly
+/// bypasses the counters.
+///
+/// Each field is read separately, so a snapshot taken while other threads
+/// push or pop is only approximately consistent across fields: a pair may
+/// already be counted in `push_count` but its spin not yet in `push_spins`.
+#[cfg(feature = "stats")]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CohortStats {
+    /// Number of elements pushed.
+    pub push_count: u64,
+    /// Number of elements popped.
+    pub pop_count: u64,
+    /// Number of times a blocking push waited for room.
+    pub push_spins: u64,
+    /// Number of times a blocking pop waited for data.
+    pub pop_spins: u64,
+    /// Number of times a non-blocking push sent nothing because the sending
+    /// end was full.
+    pub full_rejections: u64,
+    /// Number of times a non-blocking pop received nothing because the
+    /// receiving end was empty or held only part of what was asked for.
+    pub empty_rejections: u64,
+}
+
+/// The live counters behind [`CohortStats`].
+#[cfg(feature = "stats")]
+#[derive(Default)]
+pub(crate) struct Counters {
+    push_count: AtomicU64,
+    pop_count: AtomicU64,
+    push_spins: AtomicU64,
+    pop_spins: AtomicU64,
+    full_rejections: AtomicU64,
+    empty_rejections: AtomicU64,
+}
+
+#[cfg(feature = "stats")]
+impl Counters {
+    pub(crate) fn new() -> Self {
+        Counters::default()
+    }
+
+    pub(crate) fn pushed(&self, count: usize) {
+        self.push_count.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn popped(&self, count: usize) {
+        self.pop_count.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn push_spun(&self) {
+        self.push_spins.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn pop_spun(&self) {
+        self.pop_spins.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn rejected_full(&self) {
+        self.full_rejections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn rejected_empty(&self) {
+        self.empty_rejections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> CohortStats {
+        CohortStats {
+            push_count: self.push_count.load(Ordering::Relaxed),
+            pop_count: self.pop_count.load(Ordering::Relaxed),
+            push_spins: self.push_spins.load(Ordering::Relaxed),
+            pop_spins: self.pop_spins.load(Ordering::Relaxed),
+            full_rejections: self.full_rejections.load(Ordering::Relaxed),
+            empty_rejections: self.empty_rejections.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) -> CohortStats {
+        CohortStats {
+            push_count: self.push_count.swap(0, Ordering::Relaxed),
+            pop_count: self.pop_count.swap(0, Ordering::Relaxed),
+            push_spins: self.push_spins.swap(0, Ordering::Relaxed),
+            pop_spins: self.pop_spins.swap(0, Ordering::Relaxed),
+            full_rejections: self.full_rejections.swap(0, Ordering::Relaxed),
+            empty_rejections: self.empty_rejections.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Stands in for the counters when the `stats` feature is off.
+#[cfg(not(feature = "stats"))]
+pub(crate) struct Counters;
+
+#[cfg(not(feature = "stats"))]
+impl Counters {
+    pub(crate) fn new() -> Self {
+        Counters
+    }
+
+    #[inline(always)]
+    pub(crate) fn pushed(&self, _count: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn popped(&self, _count: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn push_spun(&self) {}
+
+    #[inline(always)]
+    pub(crate) fn pop_spun(&self) {}
+
+    #[inline(always)]
+    pub(crate) fn rejected_full(&self) {}
+
+    #[inline(always)]
+    pub(crate) fn rejected_empty(&self) {}
+}
@@ -0,0 +1,169 @@
+//! Raw Cohort syscalls.
+//!
+//! Test builds swap these for a stub that records every call instead of
+//! trapping into a kernel that may not have Cohort support.
+
+#[cfg(not(test))]
+use core::ffi::c_void;
+
+#[cfg(not(test))]
+const COHORT_REGISTER_SYSCALL: libc::c_long = 258;
+#[cfg(not(test))]
+const COHORT_UNREGISTER_SYSCALL: libc::c_long = 257;
+
+/// Asks the kernel to start the Cohort engine on the given queues.
+///
+/// Returns the errno on failure.
+#[cfg(not(test))]
+pub(crate) unsafe fn cohort_mn_register(
+    sender: *const c_void,
+    receiver: *const c_void,
+    custom_data: *const c_void,
+    backoff: u64,
+) -> Result<(), i32> {
+    check(libc::syscall(
+        COHORT_REGISTER_SYSCALL,
+        sender,
+        receiver,
+        custom_data,
+        backoff,
+    ))
+}
+
+/// Asks the kernel to tear down the Cohort engine with the given id.
+///
+/// Returns the errno on failure.
+#[cfg(not(test))]
+pub(crate) unsafe fn cohort_mn_unregister(id: u8) -> Result<(), i32> {
+    check(libc::syscall(COHORT_UNREGISTER_SYSCALL, id as libc::c_long))
+}
+
+#[cfg(not(test))]
+fn check(ret: libc::c_long) -> Result<(), i32> {
+    if ret < 0 {
+        Err(std::io::Error::last_os_error().raw_os_error().unwrap_or(0))
+    } else {
+        Ok(())
+    }
+}
+
+/// Restricts the calling thread to run only on `cpu`.
+///
+/// Returns the errno on failure. Not stubbed in tests.
+#[cfg(target_os = "linux")]
+pub(crate) fn pin_current_thread(cpu: usize) -> Result<(), i32> {
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(libc::EINVAL);
+    }
+    unsafe {
+        let mut set: libc::cpu_set_t = core::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        if libc::sched_setaffinity(0, core::mem::size_of::<libc::cpu_set_t>(), &set) < 0 {
+            return Err(std::io::Error::last_os_error().raw_os_error().unwrap_or(0));
+        }
+    }
+    Ok(())
+}
+
+/// Creates a non-blocking eventfd with a count of zero.
+///
+/// Returns the errno on failure. Not stubbed in tests.
+#[cfg(target_os = "linux")]
+pub(crate) fn eventfd() -> Result<std::os::fd::OwnedFd, i32> {
+    use std::os::fd::FromRawFd;
+
+    let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error().raw_os_error().unwrap_or(0));
+    }
+    Ok(unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) })
+}
+
+/// Adds one to the count of an eventfd, making it readable.
+#[cfg(all(target_os = "linux", feature = "mock"))]
+pub(crate) fn eventfd_signal(fd: std::os::fd::BorrowedFd<'_>) {
+    use std::os::fd::AsRawFd;
+
+    // Only fails if the count would overflow, in which case it is readable anyway.
+    let one = 1u64;
+    unsafe { libc::write(fd.as_raw_fd(), (&one as *const u64).cast(), 8) };
+}
+
+/// Resets the count of an eventfd to zero, so it is no longer readable.
+#[cfg(target_os = "linux")]
+pub(crate) fn eventfd_clear(fd: std::os::fd::BorrowedFd<'_>) {
+    use std::os::fd::AsRawFd;
+
+    // Fails with EAGAIN if the count was already zero.
+    let mut count = 0u64;
+    unsafe { libc::read(fd.as_raw_fd(), (&mut count as *mut u64).cast(), 8) };
+}
+
+#[cfg(test)]
+pub(crate) use stub::{cohort_mn_register, cohort_mn_unregister};
+
+#[cfg(test)]
+pub(crate) mod stub {
+    use core::ffi::c_void;
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    /// A syscall observed by the stub.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(crate) enum Call {
+        Register { backoff: u64 },
+        Unregister { id: u8 },
+    }
+
+    thread_local! {
+        static CALLS: RefCell<Vec<Call>> = const { RefCell::new(Vec::new()) };
+        static RESULTS: RefCell<VecDeque<Result<(), i32>>> = const { RefCell::new(VecDeque::new()) };
+        static CUSTOM_DATA: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Returns every syscall issued on this thread so far.
+    pub(crate) fn calls() -> Vec<Call> {
+        CALLS.with(|calls| calls.borrow().clone())
+    }
+
+    /// Returns the value of the custom data word seen by each register
+    /// syscall on this thread so far.
+    pub(crate) fn custom_data_at_register() -> Vec<u64> {
+        CUSTOM_DATA.with(|values| values.borrow().clone())
+    }
+
+    /// Makes the next syscall on this thread fail with `errno`.
+    ///
+    /// Syscalls succeed when nothing has been queued.
+    pub(crate) fn fail_next(errno: i32) {
+        RESULTS.with(|results| results.borrow_mut().push_back(Err(errno)));
+    }
+
+    /// Makes the next syscall on this thread succeed, for queueing a
+    /// failure behind it with [`fail_next`].
+    pub(crate) fn succeed_next() {
+        RESULTS.with(|results| results.borrow_mut().push_back(Ok(())));
+    }
+
+    fn record(call: Call) -> Result<(), i32> {
+        CALLS.with(|calls| calls.borrow_mut().push(call));
+        RESULTS.with(|results| results.borrow_mut().pop_front().unwrap_or(Ok(())))
+    }
+
+    pub(crate) unsafe fn cohort_mn_register(
+        _sender: *const c_void,
+        _receiver: *const c_void,
+        custom_data: *const c_void,
+        backoff: u64,
+    ) -> Result<(), i32> {
+        // Read the word like the kernel would, while the call is in progress.
+        let value = unsafe { (*custom_data.cast::<AtomicU64>()).load(Ordering::Acquire) };
+        CUSTOM_DATA.with(|values| values.borrow_mut().push(value));
+        record(Call::Register { backoff })
+    }
+
+    pub(crate) unsafe fn cohort_mn_unregister(id: u8) -> Result<(), i32> {
+        record(Call::Unregister { id })
+    }
+}
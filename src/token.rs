@@ -0,0 +1,171 @@
+//! Capabilities that let the compiler enforce a single producer and a
+//! single consumer.
+//!
+//! The pushes and pops on [`Cohort`] itself are unsafe, as nothing stops two
+//! threads from calling them at once. Going through a [`Producer`] and a
+//! [`Consumer`] makes the same calls safe.
+
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::sync::atomic::Ordering;
+
+use crate::error::Result;
+use crate::Cohort;
+
+/// The right to push onto a [`Cohort`], taken with [`Cohort::producer`].
+///
+/// A cohort hands out at most one producer at a time, and a producer can be
+/// moved to another thread but not shared between threads, so every push
+/// made through it comes from one thread at a time. The right is given back
+/// when the producer is dropped.
+pub struct Producer<'a, S: Copy, R: Copy = S> {
+    cohort: &'a Cohort<S, R>,
+    // Opts out of Sync.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+/// The right to pop from a [`Cohort`], taken with [`Cohort::consumer`].
+///
+/// Like [`Producer`], a cohort hands out at most one consumer at a time and
+/// a consumer cannot be shared between threads.
+pub struct Consumer<'a, S: Copy, R: Copy = S> {
+    cohort: &'a Cohort<S, R>,
+    // Opts out of Sync.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<S: Copy, R: Copy> Cohort<S, R> {
+    /// Takes the right to push onto the cohort.
+    ///
+    /// Returns `None` while another [`Producer`] is alive.
+    pub fn producer(&self) -> Option<Producer<'_, S, R>> {
+        if self.producer_taken.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        Some(Producer {
+            cohort: self,
+            _not_sync: PhantomData,
+        })
+    }
+
+    /// Takes the right to pop from the cohort.
+    ///
+    /// Returns `None` while another [`Consumer`] is alive.
+    pub fn consumer(&self) -> Option<Consumer<'_, S, R>> {
+        if self.consumer_taken.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        Some(Consumer {
+            cohort: self,
+            _not_sync: PhantomData,
+        })
+    }
+}
+
+// SAFETY: For the pushes below, the producer is the only one of its cohort
+// and is only ever used from one thread at a time.
+impl<S: Copy, R: Copy> Producer<'_, S, R> {
+    /// Sends an element to the accelerator.
+    ///
+    /// May block if the sending end is full.
+    pub fn push(&self, elem1: &S, elem2: &S) {
+        unsafe { self.cohort.push(elem1, elem2) };
+    }
+
+    /// Sends an element to the accelerator.
+    ///
+    /// Will fail if the sending end is full.
+    pub fn try_push(&self, elem1: &S, elem2: &S) -> Result<()> {
+        unsafe { self.cohort.try_push(elem1, elem2) }
+    }
+
+    /// Sends a single element to the accelerator.
+    ///
+    /// May block if the sending end is full.
+    pub fn push_one(&self, elem: &S) {
+        unsafe { self.cohort.push_one(elem) };
+    }
+
+    /// Sends a single element to the accelerator.
+    ///
+    /// Will fail if the sending end is full.
+    pub fn try_push_one(&self, elem: &S) -> Result<()> {
+        unsafe { self.cohort.try_push_one(elem) }
+    }
+
+    /// Makes every element pushed so far visible to the accelerator.
+    pub fn flush(&self) {
+        self.cohort.flush();
+    }
+}
+
+// SAFETY: As for the producer, for the pops below.
+impl<S: Copy, R: Copy> Consumer<'_, S, R> {
+    /// Receives an element from the accelerator.
+    ///
+    /// May block if the receiving end is empty.
+    pub fn pop(&self, elem1: &mut R, elem2: &mut R) {
+        unsafe { self.cohort.pop(elem1, elem2) };
+    }
+
+    /// Receives an element from the accelerator.
+    ///
+    /// Will fail if the receiving end is empty.
+    pub fn try_pop(&self, elem1: &mut R, elem2: &mut R) -> Result<()> {
+        unsafe { self.cohort.try_pop(elem1, elem2) }
+    }
+
+    /// Receives a single element from the accelerator.
+    ///
+    /// May block if the receiving end is empty.
+    pub fn pop_one(&self, elem: &mut R) {
+        unsafe { self.cohort.pop_one(elem) };
+    }
+
+    /// Receives a single element from the accelerator.
+    ///
+    /// Will fail if the receiving end is empty.
+    pub fn try_pop_one(&self, elem: &mut R) -> Result<()> {
+        unsafe { self.cohort.try_pop_one(elem) }
+    }
+}
+
+impl<S: Copy, R: Copy> Drop for Producer<'_, S, R> {
+    fn drop(&mut self) {
+        self.cohort.producer_taken.store(false, Ordering::Release);
+    }
+}
+
+impl<S: Copy, R: Copy> Drop for Consumer<'_, S, R> {
+    fn drop(&mut self) {
+        self.cohort.consumer_taken.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::Cohort;
+
+    #[test]
+    fn one_producer_and_consumer_at_a_time() {
+        let cohort = Cohort::<u64>::new(0, 16, 2).unwrap();
+        let producer = cohort.producer().unwrap();
+        assert!(cohort.producer().is_none());
+        let consumer = cohort.consumer().unwrap();
+        assert!(cohort.consumer().is_none());
+
+        thread::scope(|s| {
+            s.spawn(move || producer.push(&1, &2));
+        });
+        cohort.loopback();
+        let (mut a, mut b) = (0, 0);
+        consumer.pop(&mut a, &mut b);
+        assert_eq!((a, b), (1, 2));
+
+        assert!(cohort.producer().is_some());
+        drop(consumer);
+        assert!(cohort.consumer().is_some());
+    }
+}
@@ -1,2 +1,166 @@
+/// Wraps a value so that it starts on its own 128-byte boundary.
+///
+/// This is the cacheline size Cohort expects between the hardware-visible
+/// queue fields.
 #[repr(C, align(128))]
 pub struct Aligned<T>(pub T);
+
+/// Wraps a value so that it starts on an `N`-byte boundary.
+///
+/// For accelerators whose cacheline size differs from the 128 bytes of
+/// [`Aligned`]. `N` may be any power of two from 8 to 4096.
+#[repr(C)]
+pub struct AlignedTo<T, const N: usize>
+where
+    Alignment<N>: SupportedAlignment,
+{
+    _align: [<Alignment<N> as SupportedAlignment>::Marker; 0],
+    /// The wrapped value.
+    pub value: T,
+}
+
+impl<T, const N: usize> AlignedTo<T, N>
+where
+    Alignment<N>: SupportedAlignment,
+{
+    /// Wraps `value`.
+    pub const fn new(value: T) -> Self {
+        AlignedTo { _align: [], value }
+    }
+}
+
+/// Names an alignment of `N` bytes for [`AlignedTo`].
+pub struct Alignment<const N: usize>;
+
+/// Implemented for every [`Alignment`] that [`AlignedTo`] supports.
+pub trait SupportedAlignment {
+    /// A zero-sized type with the alignment.
+    type Marker;
+}
+
+macro_rules! supported_alignments {
+    ($($n:literal => $marker:ident),* $(,)?) => {
+        $(
+            #[doc(hidden)]
+            #[repr(align($n))]
+            pub struct $marker;
+
+            impl SupportedAlignment for Alignment<$n> {
+                type Marker = $marker;
+            }
+        )*
+    };
+}
+
+supported_alignments! {
+    8 => Align8,
+    16 => Align16,
+    32 => Align32,
+    64 => Align64,
+    128 => Align128,
+    256 => Align256,
+    512 => Align512,
+    1024 => Align1024,
+    2048 => Align2048,
+    4096 => Align4096,
+}
+
+// The queue layout Cohort reads relies on this.
+const _: () = assert!(core::mem::align_of::<Aligned<u32>>() == 128);
+
+/// Default number of spin rounds a blocking call makes before it starts yielding.
+pub const DEFAULT_SPINS_BEFORE_YIELD: u32 = 6;
+
+/// Each spin round spins twice as long as the last, up to this many doublings.
+const MAX_SPIN_SHIFT: u32 = 6;
+
+/// Escalating wait used by the blocking queue operations.
+///
+/// The first few waits busy-spin for exponentially longer stretches, which
+/// keeps latency low when the accelerator answers quickly. Once
+/// `spins_before_yield` rounds have passed every wait yields the thread instead,
+/// or keeps spinning at the longest stretch without the `std` feature, where
+/// there is no thread to yield.
+pub struct Backoff {
+    step: u32,
+    spins_before_yield: u32,
+}
+
+impl Backoff {
+    /// Starts a wait that spins for `spins_before_yield` rounds before yielding.
+    pub fn new(spins_before_yield: u32) -> Self {
+        Backoff {
+            step: 0,
+            spins_before_yield,
+        }
+    }
+
+    /// Waits a little before the caller retries.
+    pub fn snooze(&mut self) {
+        if self.step < self.spins_before_yield {
+            for _ in 0..1u32 << self.step.min(MAX_SPIN_SHIFT) {
+                core::hint::spin_loop();
+            }
+            self.step += 1;
+        } else {
+            #[cfg(any(test, feature = "std"))]
+            std::thread::yield_now();
+            #[cfg(not(any(test, feature = "std")))]
+            for _ in 0..1u32 << MAX_SPIN_SHIFT {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
+/// Hints to the CPU that the cache line holding `ptr` is about to be read.
+///
+/// With the `prefetch` feature this issues `prefetcht0` on x86 with SSE and
+/// `prfm pldl1keep` on aarch64. It compiles to nothing on other
+/// architectures and without the feature. A prefetch never faults, so any
+/// address is fine, though one outside the buffer only wastes the hint.
+#[inline(always)]
+pub(crate) fn prefetch_read<T>(ptr: *const T) {
+    #[cfg(all(feature = "prefetch", target_arch = "x86_64"))]
+    unsafe {
+        core::arch::x86_64::_mm_prefetch::<{ core::arch::x86_64::_MM_HINT_T0 }>(ptr.cast());
+    }
+    #[cfg(all(feature = "prefetch", target_arch = "x86", target_feature = "sse"))]
+    unsafe {
+        core::arch::x86::_mm_prefetch::<{ core::arch::x86::_MM_HINT_T0 }>(ptr.cast());
+    }
+    #[cfg(all(feature = "prefetch", target_arch = "aarch64"))]
+    unsafe {
+        core::arch::asm!(
+            "prfm pldl1keep, [{ptr}]",
+            ptr = in(reg) ptr,
+            options(nostack, readonly, preserves_flags)
+        );
+    }
+    let _ = ptr;
+}
+
+#[cfg(test)]
+mod tests {
+    use core::mem::{align_of, size_of};
+    use core::sync::atomic::AtomicU64;
+
+    use super::{Aligned, AlignedTo};
+
+    #[test]
+    fn aligned_is_128_bytes() {
+        assert_eq!(align_of::<Aligned<AtomicU64>>(), 128);
+        assert_eq!(size_of::<Aligned<AtomicU64>>(), 128);
+    }
+
+    #[test]
+    fn aligned_to_uses_requested_alignment() {
+        assert_eq!(align_of::<AlignedTo<AtomicU64, 64>>(), 64);
+        assert_eq!(size_of::<AlignedTo<AtomicU64, 64>>(), 64);
+        assert_eq!(align_of::<AlignedTo<u8, 4096>>(), 4096);
+
+        let aligned = AlignedTo::<u32, 64>::new(7);
+        assert_eq!(aligned.value, 7);
+        assert_eq!(&aligned as *const _ as usize % 64, 0);
+    }
+}
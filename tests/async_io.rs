@@ -0,0 +1,33 @@
+//! Async pushes and pops against the software accelerator of the `mock`
+//! feature.
+
+#![cfg(all(feature = "async", feature = "mock"))]
+
+use cohort::{CohortBuilder, LoopbackAccelerator};
+
+#[tokio::test]
+async fn async_calls_yield_to_each_other() {
+    const COUNT: u64 = 64;
+    // Far smaller than the stream, so both sides have to wait on each other.
+    let config = CohortBuilder::new().capacity(4).batch_size(2);
+    let accel = LoopbackAccelerator::<u64>::new(&config).unwrap();
+    let cohort = accel.cohort();
+
+    let producer = async {
+        for i in (0..COUNT).step_by(2) {
+            unsafe { cohort.push_async(&i, &(i + 1)) }.await;
+        }
+    };
+    let consumer = async {
+        let mut received = Vec::new();
+        for _ in (0..COUNT).step_by(2) {
+            let (mut a, mut b) = (0, 0);
+            unsafe { cohort.pop_async(&mut a, &mut b) }.await;
+            received.extend([a, b]);
+        }
+        received
+    };
+    let ((), received) = tokio::join!(producer, consumer);
+    assert!(received.into_iter().eq(0..COUNT));
+    assert!(cohort.receiver_is_empty());
+}
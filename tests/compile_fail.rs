@@ -0,0 +1,6 @@
+//! Misuses of a registered cohort that must not compile.
+
+#[test]
+fn compile_fail() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
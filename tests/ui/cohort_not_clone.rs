@@ -0,0 +1,6 @@
+use cohort::Cohort;
+
+fn main() {
+    let cohort = unsafe { Cohort::<u64>::register(0, 32, 8) }.unwrap();
+    let _copy = (*cohort).clone();
+}
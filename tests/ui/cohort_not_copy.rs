@@ -0,0 +1,7 @@
+use cohort::Cohort;
+
+fn assert_copy<T: Copy>() {}
+
+fn main() {
+    assert_copy::<Cohort<u64>>();
+}
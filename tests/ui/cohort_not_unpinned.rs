@@ -0,0 +1,8 @@
+use std::pin::Pin;
+
+use cohort::Cohort;
+
+fn main() {
+    let cohort = unsafe { Cohort::<u64>::register(0, 32, 8) }.unwrap();
+    let _moved: Box<Cohort<u64>> = Pin::into_inner(cohort);
+}
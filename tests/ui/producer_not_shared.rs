@@ -0,0 +1,13 @@
+use std::thread;
+
+use cohort::Cohort;
+
+fn main() {
+    let cohort = Cohort::<u64>::new(0, 32, 8).unwrap();
+    let producer = cohort.producer().unwrap();
+    // Two threads pushing through the same producer would race.
+    thread::scope(|s| {
+        s.spawn(|| producer.push(&1, &2));
+        s.spawn(|| producer.push(&3, &4));
+    });
+}
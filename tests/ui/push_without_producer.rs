@@ -0,0 +1,9 @@
+use cohort::Cohort;
+
+fn main() {
+    let cohort = Cohort::<u64>::new(0, 32, 8).unwrap();
+    let producer = cohort.producer().unwrap();
+    producer.push(&1, &2);
+    // A second push that bypasses the producer could race with it.
+    cohort.push(&3, &4);
+}